@@ -1,27 +1,48 @@
+use std::collections::HashMap;
+use std::ops::RangeBounds;
 use std::path::PathBuf;
-use std::sync::RwLock;
+use std::sync::{Arc, Mutex, RwLock};
 use std::{net::UdpSocket, path::Path};
 
 use lazy_static::lazy_static;
-use redb::{CacheStats, Database, ReadableDatabase, TableDefinition};
+use redb::{
+    CacheStats, Database, ReadOnlyTable, ReadableDatabase, ReadableTable, ReadableTableMetadata,
+    Table, TableDefinition, TableError,
+};
+use rune::termcolor::{Color, ColorSpec, NoColor, WriteColor};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::Value;
 
-use crate::system::System;
-
-lazy_static! {
-    pub static ref SYSTEM: RwLock<System> = RwLock::new(System::new());
-}
+use crate::groups::Routing;
 
 pub mod dirs {
     pub const LOGS: &str = "logs";
     pub const STORAGE: &str = "storage";
+    /// below [`STORAGE`], holds one db per actor, named after the actor id
+    pub const ACTOR_DBS: &str = "actors";
 }
 pub const KILL_FILE: &str = "/tmp/eos_kill";
 #[allow(dead_code)]
 pub const EOS_9P_PORT: u16 = 7797;
 pub const EOS_RPC_PORT: u16 = 7780;
 pub const DEFAULT_TICK: u64 = 2000;
+pub const DEFAULT_MAILBOX_BUDGET: usize = 1;
+/// how many dead letters are kept before the oldest ones are dropped
+pub const MAX_DEAD_LETTERS: usize = 1000;
+pub const DEFAULT_MAX_RESTARTS: u32 = 3;
+pub const DEFAULT_RESTART_WINDOW: u64 = 10;
+/// how many ticks `eos ask` waits for a reply by default
+pub const DEFAULT_ASK_TIMEOUT: u64 = 10;
+/// the db holding snapshots of the whole system
+pub const SNAPSHOT_DB: &str = "snapshots";
+/// the snapshot written periodically and on shutdown
+pub const LATEST_SNAPSHOT: &str = "latest";
+/// seconds between two periodic snapshots
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 60;
+/// how many past ticks are kept in memory for `eos tick back` and `/history`
+pub const MAX_HISTORY: usize = 100;
+/// how many events a watcher may fall behind before it misses some
+pub const EVENT_BUFFER: usize = 1024;
 
 const TELEPLOT_ADDR: &str = "127.0.0.1:47269";
 const TABLE: TableDefinition<&str, String> = TableDefinition::new("DATA");
@@ -29,22 +50,426 @@ const TABLE: TableDefinition<&str, String> = TableDefinition::new("DATA");
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Response {
     Done,
-    Failed { err: String },
-    Spawned { id: String },
-    Actors { actors: Vec<String> },
+    Failed {
+        err: String,
+        /// what the compiler found in a script that didn't compile
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        diagnostics: Vec<Diagnostic>,
+    },
+    Spawned {
+        id: String,
+    },
+    Actors {
+        actors: Vec<String>,
+    },
+    Policy {
+        policy: TickPolicy,
+    },
+    DeadLetters {
+        dead_letters: Vec<DeadLetter>,
+    },
+    Tick {
+        tick: u64,
+    },
+    Reloaded {
+        actors: Vec<String>,
+    },
+    /// the answer to an `eos ask`
+    Reply {
+        payload: Value,
+    },
+    /// the group made by `eos group create`
+    GroupCreated {
+        id: String,
+        members: Vec<String>,
+    },
+    /// the actors a message published with `eos publish` was delivered to
+    Published {
+        actors: Vec<String>,
+    },
+    /// the text a command prints
+    Output {
+        output: String,
+    },
+}
+
+impl Response {
+    pub fn failed(err: impl ToString) -> Self {
+        Response::Failed {
+            err: err.to_string(),
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+/// A problem the compiler found in a script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// the file the problem is in, `<memory>` for inline source
+    pub file: String,
+    /// where in the file, `None` if the compiler didn't say
+    pub span: Option<Span>,
+    pub message: String,
+    /// the line `span` starts on
+    pub code: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A range of a source file, lines and columns count from 1
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic like the compiler does, with colors if `out` supports them
+    pub fn emit(&self, out: &mut impl WriteColor) -> std::io::Result<()> {
+        let (label, color) = match self.severity {
+            Severity::Error => ("error", Color::Red),
+            Severity::Warning => ("warning", Color::Yellow),
+        };
+        out.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        write!(out, "{label}")?;
+        out.set_color(ColorSpec::new().set_bold(true))?;
+        writeln!(out, ": {}", self.message)?;
+        out.reset()?;
+        let Some(span) = self.span else {
+            return writeln!(out, "  --> {}", self.file);
+        };
+        let gutter = " ".repeat(span.line.to_string().len());
+        let blue = ColorSpec::new()
+            .set_fg(Some(Color::Blue))
+            .set_bold(true)
+            .clone();
+        out.set_color(&blue)?;
+        write!(out, "{gutter}--> ")?;
+        out.reset()?;
+        writeln!(out, "{}:{}:{}", self.file, span.line, span.column)?;
+        out.set_color(&blue)?;
+        writeln!(out, "{gutter} |")?;
+        write!(out, "{} | ", span.line)?;
+        out.reset()?;
+        writeln!(out, "{}", self.code)?;
+        out.set_color(&blue)?;
+        write!(out, "{gutter} | ")?;
+        // a span over several lines is marked up to the end of its first line
+        let width = if span.end_line == span.line {
+            span.end_column.saturating_sub(span.column)
+        } else {
+            self.code.chars().count().saturating_sub(span.column - 1)
+        };
+        out.set_color(ColorSpec::new().set_fg(Some(color)).set_bold(true))?;
+        writeln!(
+            out,
+            "{}{}",
+            " ".repeat(span.column - 1),
+            "^".repeat(width.max(1))
+        )?;
+        out.reset()
+    }
+}
+
+/// Renders `diagnostics` without colors, see [`Diagnostic::emit`]
+pub fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    let mut out = NoColor::new(Vec::new());
+    for diagnostic in diagnostics {
+        // writing to a Vec can't fail
+        _ = diagnostic.emit(&mut out);
+    }
+    String::from_utf8_lossy(&out.into_inner()).into_owned()
+}
+
+/// An operation on the db `name`, run by the server if it is up and locally otherwise
+///
+/// The server keeps its dbs open, so they can't be opened by another process meanwhile.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DbCall {
+    pub name: String,
+    pub request: DbRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum DbRequest {
+    Store {
+        key: String,
+        value: Value,
+    },
+    Delete {
+        key: String,
+    },
+    Load {
+        key: String,
+    },
+    Exists {
+        key: String,
+    },
+    Keys {
+        prefix: String,
+    },
+    Count,
+    /// all entries with a key from `from` (inclusive) to `to` (exclusive)
+    Dump {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    Import {
+        entries: serde_json::Map<String, Value>,
+        /// skip keys that already exist instead of overwriting them
+        keep_existing: bool,
+    },
+    Compact,
+    Stats,
+}
+
+/// Replaces the live system with the one at the end of `tick`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForkRequest {
+    pub tick: u64,
+    /// stores the abandoned timeline as a snapshot with this name
+    pub keep: Option<String>,
+}
+
+/// Rebuilds the system recorded in `journal`, see `journal::replay`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplayRequest {
+    pub journal: String,
+    /// the last tick to replay, the end of the journal if `None`
+    pub until_tick: Option<u64>,
+    /// stores the rebuilt system as a snapshot with this name
+    pub save: Option<String>,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// Sends `payload` to `to` and waits up to `timeout_ticks` ticks for the reply
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AskRequest {
+    pub to: String,
+    pub payload: Value,
+    pub timeout_ticks: u64,
+}
+
+/// Publishes `payload` on `topic`, see `System::publish`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublishRequest {
+    pub topic: String,
+    pub payload: Value,
+}
+
+/// Spawns a group of `size` actors running the same script, see `System::create_group`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GroupRequest {
+    pub id: Option<String>,
+    /// the source of the script, unused if `file` is set
+    #[serde(default)]
+    pub script: String,
+    pub file: Option<String>,
+    pub size: u32,
+    pub routing: Routing,
+    pub hash_key: Option<String>,
+}
+
+/// Replaces the script of `actor`, see `System::reload_actor`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReloadRequest {
+    pub actor: String,
+    pub script: String,
+    /// also reloads every other actor running the same script as `actor`
+    pub shared: bool,
+}
+
+/// Controls how many messages are handled per tick
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TickPolicy {
+    /// how many messages each actor handles per tick
+    pub mailbox_budget: usize,
+    /// handle every message that is in a mailbox at the start of the tick, ignoring `mailbox_budget`
+    pub drain_all: bool,
+    /// how many messages are handled per tick across all actors, unlimited if `None`
+    pub global_budget: Option<usize>,
+}
+
+impl Default for TickPolicy {
+    fn default() -> Self {
+        Self {
+            mailbox_budget: DEFAULT_MAILBOX_BUDGET,
+            drain_all: false,
+            global_budget: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Props {
+    /// the source of the script, unused if `file` is set
+    #[serde(default)]
     pub script: String,
+    /// a library script to run instead of `script`, see `scripts::find_script`
+    #[serde(default)]
+    pub file: Option<String>,
     pub id: Option<String>,
+    /// the actor that requested the spawn, if it was spawned from a script
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// the actor that restarts this one when its handler fails
+    #[serde(default)]
+    pub supervisor: Option<String>,
+    /// how this actor restarts the actors it supervises
+    #[serde(default)]
+    pub supervision: SupervisorSpec,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Which actors a supervisor restarts when one of its children fails
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartStrategy {
+    /// only the failing child
+    #[default]
+    OneForOne,
+    /// all children of the supervisor
+    OneForAll,
+    /// the failing child and every child started after it
+    RestForOne,
+}
+
+/// Restart strategy and intensity of a supervisor
+///
+/// If more than `max_restarts` restarts happen within `within_ticks` ticks, the supervisor
+/// gives up and the affected children are killed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SupervisorSpec {
+    pub strategy: RestartStrategy,
+    pub max_restarts: u32,
+    pub within_ticks: u64,
+}
+
+impl Default for SupervisorSpec {
+    fn default() -> Self {
+        Self {
+            strategy: RestartStrategy::default(),
+            max_restarts: DEFAULT_MAX_RESTARTS,
+            within_ticks: DEFAULT_RESTART_WINDOW,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Message {
     pub from: Option<String>,
     pub to: String,
     pub payload: Value,
+    /// ties a request made with `ask` to its reply or timeout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    /// where the reply goes instead of `from`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<String>,
+    #[serde(default, skip_serializing_if = "MessageKind::is_tell")]
+    pub kind: MessageKind,
+    /// the topic the message was published on; with an empty `to`, the message is
+    /// still on its way to the subscribers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+}
+
+impl Message {
+    /// Where a response to this message goes, `None` if it can't be answered
+    pub fn reply_address(&self) -> Option<&str> {
+        self.reply_to.as_deref().or(self.from.as_deref())
+    }
+}
+
+/// Which handler of the receiving actor runs for a message
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    /// handled by `handle`
+    #[default]
+    Tell,
+    /// the answer to an `ask`, handled by `handle_reply`
+    Reply,
+    /// no answer to an `ask` arrived in time, handled by `handle_timeout`
+    Timeout,
+    /// a watched or linked actor terminated, handled by `handle_terminated`
+    Terminated,
+}
+
+impl MessageKind {
+    fn is_tell(&self) -> bool {
+        *self == MessageKind::Tell
+    }
+}
+
+/// What happens to a message when an actor's `handle` fails on it
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorPolicy {
+    /// pause the actor and keep the message at the front of its mailbox
+    Pause,
+    /// move the message to the dead letters
+    #[default]
+    Drop,
+    /// keep the message and retry it up to `times` times before moving it to the dead letters
+    Retry { times: u32 },
+}
+
+/// Why a message could not be delivered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DeadLetterReason {
+    /// no actor with the id in `to` exists
+    UnknownActor,
+    /// the actor was killed while the message was still in its mailbox
+    ActorKilled,
+    /// the actor's `handle` failed on the message
+    HandlerError { error: String },
+    /// a reply to an `ask` the receiver isn't waiting for (anymore)
+    UnexpectedReply,
+}
+
+/// Why an actor terminated, as told to its watchers and the actors linked to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TerminationReason {
+    /// it was killed, or its supervisor was
+    Killed,
+    /// its supervisor gave up restarting it after its `handle` failed
+    Crashed { error: String },
+    /// it called `exit()`
+    Exited,
+    /// an actor it was linked to terminated
+    Linked { actor: String },
+    /// there was no such actor when it was watched or linked
+    UnknownActor,
+}
+
+/// A message that could not be delivered, together with the reason and the tick it happened in
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub message: Message,
+    pub reason: DeadLetterReason,
+    pub tick: u64,
+}
+
+/// The 64 bit FNV-1a hash of `bytes`
+///
+/// Unlike `DefaultHasher`, it is the same across builds and restarts.
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    bytes.into_iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
 }
 
 pub fn teleplot(value: &str) -> anyhow::Result<()> {
@@ -53,7 +478,12 @@ pub fn teleplot(value: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
-#[derive(Clone)]
+lazy_static! {
+    /// Databases opened by this process, so every file is opened only once
+    static ref DATABASES: Mutex<HashMap<PathBuf, Arc<RwLock<Database>>>> = Default::default();
+}
+
+#[derive(Debug, Clone)]
 pub struct Db {
     storage_dir: PathBuf,
     name: String,
@@ -67,56 +497,208 @@ impl Db {
         }
     }
 
-    fn db(&self) -> anyhow::Result<Database> {
-        Ok(Database::create(self.storage_dir.join(&self.name))?)
+    pub fn path(&self) -> PathBuf {
+        self.storage_dir.join(&self.name)
+    }
+
+    /// Returns the shared handle of this db, opening it on first use
+    fn db(&self) -> anyhow::Result<Arc<RwLock<Database>>> {
+        let mut databases = DATABASES.lock().unwrap();
+        let path = self.path();
+        if let Some(db) = databases.get(&path) {
+            return Ok(db.clone());
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let db = Arc::new(RwLock::new(Database::create(&path)?));
+        databases.insert(path, db.clone());
+        Ok(db)
+    }
+
+    /// Drops the shared handle of this db, the file is opened again on next use
+    ///
+    /// The file stays locked until every handle still in use is dropped.
+    pub fn close(&self) {
+        DATABASES.lock().unwrap().remove(&self.path());
+    }
+
+    /// Drops the shared handles of all dbs in `storage_dir`
+    pub fn close_all(storage_dir: impl AsRef<Path>) {
+        DATABASES
+            .lock()
+            .unwrap()
+            .retain(|path, _| !path.starts_with(&storage_dir));
+    }
+
+    /// Closes the db and deletes its file
+    pub fn remove(&self) -> anyhow::Result<()> {
+        let path = self.path();
+        self.close();
+        if std::fs::exists(&path)? {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
     }
 
     pub fn stats(&self) -> anyhow::Result<CacheStats> {
-        Ok(self.db()?.cache_stats())
+        Ok(self.db()?.read().unwrap().cache_stats())
     }
 
     pub fn compact(&self) -> anyhow::Result<bool> {
-        Ok(self.db()?.compact()?)
+        Ok(self.db()?.write().unwrap().compact()?)
     }
 
-    pub fn store<T: Serialize + DeserializeOwned>(
+    /// Runs `f` in a write transaction, which is committed if `f` succeeds and aborted otherwise
+    pub fn transaction<R>(
         &self,
-        key: &str,
-        value: T,
-    ) -> anyhow::Result<()> {
-        let write_txn = self.db()?.begin_write()?;
-        {
-            let mut table = write_txn.open_table(TABLE)?;
-            table.insert(key, serde_json::to_string(&value)?)?;
+        f: impl FnOnce(&mut Transaction) -> anyhow::Result<R>,
+    ) -> anyhow::Result<R> {
+        let db = self.db()?;
+        let write_txn = db.read().unwrap().begin_write()?;
+        let result = {
+            let mut transaction = Transaction {
+                table: write_txn.open_table(TABLE)?,
+            };
+            f(&mut transaction)
+        };
+        match result {
+            Ok(result) => {
+                write_txn.commit()?;
+                Ok(result)
+            }
+            Err(e) => {
+                write_txn.abort()?;
+                Err(e)
+            }
         }
-        write_txn.commit()?;
-        Ok(())
+    }
+
+    /// Stores all `entries` in a single transaction and returns how many there were
+    pub fn batch<T: Serialize>(
+        &self,
+        entries: impl IntoIterator<Item = (String, T)>,
+    ) -> anyhow::Result<usize> {
+        self.transaction(|transaction| {
+            let mut count = 0;
+            for (key, value) in entries {
+                transaction.store(&key, value)?;
+                count += 1;
+            }
+            Ok(count)
+        })
+    }
+
+    pub fn store<T: Serialize>(&self, key: &str, value: T) -> anyhow::Result<()> {
+        self.transaction(|transaction| transaction.store(key, value))
     }
 
     pub fn delete(&self, key: &str) -> anyhow::Result<()> {
-        let write_txn = self.db()?.begin_write()?;
-        {
-            let mut table = write_txn.open_table(TABLE)?;
-            table.remove(key)?;
+        self.transaction(|transaction| transaction.delete(key))
+    }
+
+    /// Runs `f` on the table of a read transaction, or returns the default if it has no table yet
+    fn read<R: Default>(
+        &self,
+        f: impl FnOnce(&ReadOnlyTable<&'static str, String>) -> anyhow::Result<R>,
+    ) -> anyhow::Result<R> {
+        let db = self.db()?;
+        let read_txn = db.read().unwrap().begin_read()?;
+        match read_txn.open_table(TABLE) {
+            Ok(table) => f(&table),
+            Err(TableError::TableDoesNotExist(_)) => Ok(R::default()),
+            Err(e) => Err(e.into()),
         }
-        write_txn.commit()?;
-        Ok(())
     }
 
     pub fn load<T: DeserializeOwned>(&self, key: &str) -> anyhow::Result<Option<T>> {
-        let read_txn = self.db()?.begin_read()?;
-        let table = read_txn.open_table(TABLE)?;
-        let result = if let Some(value) = table.get(key)? {
-            Some(serde_json::from_str(&value.value())?)
-        } else {
-            None
-        };
-        Ok(result)
+        self.read(|table| {
+            Ok(match table.get(key)? {
+                Some(value) => Some(serde_json::from_str(&value.value())?),
+                None => None,
+            })
+        })
+    }
+
+    pub fn exists(&self, key: &str) -> anyhow::Result<bool> {
+        self.read(|table| Ok(table.get(key)?.is_some()))
+    }
+
+    /// Returns every entry with a key in `range`, ordered by key
+    pub fn range<'a, T: DeserializeOwned>(
+        &self,
+        range: impl RangeBounds<&'a str> + 'a,
+    ) -> anyhow::Result<Vec<(String, T)>> {
+        self.read(|table| {
+            let mut entries = Vec::new();
+            for entry in table.range(range)? {
+                let (key, value) = entry?;
+                entries.push((
+                    key.value().to_owned(),
+                    serde_json::from_str(&value.value())?,
+                ));
+            }
+            Ok(entries)
+        })
+    }
+
+    /// Returns every entry whose key starts with `prefix`, ordered by key
+    pub fn scan_prefix<T: DeserializeOwned>(
+        &self,
+        prefix: &str,
+    ) -> anyhow::Result<Vec<(String, T)>> {
+        self.read(|table| {
+            let mut entries = Vec::new();
+            for entry in table.range(prefix..)? {
+                let (key, value) = entry?;
+                let key = key.value();
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                entries.push((key.to_owned(), serde_json::from_str(&value.value())?));
+            }
+            Ok(entries)
+        })
+    }
+
+    /// Returns all keys starting with `prefix`, ordered
+    pub fn keys(&self, prefix: &str) -> anyhow::Result<Vec<String>> {
+        self.read(|table| {
+            let mut keys = Vec::new();
+            for entry in table.range(prefix..)? {
+                let (key, _) = entry?;
+                let key = key.value();
+                if !key.starts_with(prefix) {
+                    break;
+                }
+                keys.push(key.to_owned());
+            }
+            Ok(keys)
+        })
+    }
+
+    pub fn count(&self) -> anyhow::Result<u64> {
+        self.read(|table| Ok(table.len()?))
+    }
+}
+
+/// The table of a write transaction, see [`Db::transaction`]
+pub struct Transaction<'txn> {
+    table: Table<'txn, &'static str, String>,
+}
+
+impl Transaction<'_> {
+    pub fn store<T: Serialize>(&mut self, key: &str, value: T) -> anyhow::Result<()> {
+        self.table.insert(key, serde_json::to_string(&value)?)?;
+        Ok(())
+    }
+
+    pub fn delete(&mut self, key: &str) -> anyhow::Result<()> {
+        self.table.remove(key)?;
+        Ok(())
     }
 
     pub fn exists(&self, key: &str) -> anyhow::Result<bool> {
-        let read_txn = self.db()?.begin_read()?;
-        let table = read_txn.open_table(TABLE)?;
-        Ok(table.get(key)?.is_some())
+        Ok(self.table.get(key)?.is_some())
     }
 }
@@ -11,12 +11,41 @@
 //! ```text
 //! /
 //! ├── spawn_queue       # Pending actor spawn requests (read-only)
+//! ├── dead_letters      # Messages that could not be delivered (read-only)
+//! ├── events            # Stream of everything that happens in the system (read-only)
+//! ├── history/          # The system at the end of the last ticks (read-only)
+//! │   └── {tick}/
+//! │       └── actors/
+//! │           └── {actor_id}/
+//! │               └── state
+//! ├── db/               # The key-value stores in `storage/`
+//! │   └── {name}/
+//! │       ├── .stats    # Cache stats of the store (read-only)
+//! │       └── {key}     # Value stored under the key as JSON (writable, removable)
+//! ├── scripts/          # The scripts actors are running (read-only)
+//! │   └── {name}/       # Library scripts by name, inline ones by the hash of their source
+//! │       ├── source    # The script's source, of its entry file for a library script
+//! │       ├── files     # The files it was compiled from, one per line
+//! │       └── actors    # The actors running it, one per line
+//! ├── topics/           # The topics actors are subscribed to (read-only)
+//! │   └── {pattern}/    # Each pattern someone subscribed to, wildcards included
+//! │       └── subscribers # The actors subscribed to it, one per line
+//! ├── groups/           # Groups of actors addressed by a single id (read-only)
+//! │   └── {group_id}/
+//! │       ├── members   # The actors in the group, one per line
+//! │       └── routing   # How messages to the group pick members, and the hash key
 //! └── actors/           # Directory of all actors
 //!     └── {actor_id}/   # Directory for each actor
 //!         ├── mailbox   # Actor's incoming message queue (writable)
-//!         ├── script    # Path to actor's script (read-only)
+//!         ├── script    # Actor's script source (writable, reloads the actor)
 //!         ├── state     # Actor's current state in JSON (writable)
-//!         └── paused    # Actor's paused state as boolean (writable)
+//!         ├── paused    # Actor's paused state as boolean (writable)
+//!         ├── inbox     # Appends one message per written line (write-only)
+//!         ├── events    # Stream of everything that happens to the actor (read-only)
+//!         ├── error     # Last error of the actor's handler with backtrace (read-only)
+//!         ├── errors    # Why the last script written was rejected (read-only)
+//!         ├── diagnostics # Compiler diagnostics of the script as JSON (read-only)
+//!         └── timers    # Timers the actor scheduled as JSON (read-only)
 //! ```
 //!
 //! # Usage
@@ -38,8 +67,14 @@
 //! echo 'true' > /mnt/eos/actors/{id}/paused
 //! echo 'false' > /mnt/eos/actors/{id}/paused
 //!
-//! # Send a message to an actor (write JSON message)
-//! echo '{"from":"sender","to":"receiver","payload":{"data":"value"}}' > /mnt/eos/actors/{id}/mailbox
+//! # Spawn an actor, then kill it and its supervised children
+//! mkdir /mnt/eos/actors/counter
+//! cp counter.rn /mnt/eos/actors/counter/script
+//! rmdir /mnt/eos/actors/counter
+//!
+//! # Send messages to an actor (one JSON payload per line)
+//! echo '{"data":"value"}' >> /mnt/eos/actors/{id}/inbox
+//! echo '{"from":"sender","payload":{"data":"value"}}' >> /mnt/eos/actors/{id}/inbox
 //! ```
 //!
 //! # File Format
@@ -49,6 +84,24 @@
 //! - Message queues show detailed message information
 //! - Paused state is a boolean string ("true" or "false")
 //!
+//! # Event Streams
+//!
+//! Reading an `events` file blocks until something happens and returns one JSON
+//! object per line, tagged with its `kind`: `delivered`, `state_changed`, `spawned`,
+//! `killed`, `failed` and `tick`. Each open file receives the events from the time it
+//! was opened, so keep it open to follow the system:
+//!
+//! ```bash
+//! cat /mnt/eos/actors/{id}/events
+//! ```
+//!
+//! `/actors/{id}/events` only returns the events about that actor. A read waits for
+//! at most [`EVENTS_HEARTBEAT`] and returns a `heartbeat` line if nothing happened by
+//! then: rs9p keeps the fid table of a connection locked while one of its requests
+//! runs, so a read that waits longer would hold up walking to and clunking every
+//! other file of the mount. A reader that falls too far behind gets a `lagged` line
+//! with the number of events it missed.
+//!
 //! # Write Buffering
 //!
 //! The filesystem implements write buffering to support editors like `vi` that perform
@@ -56,6 +109,8 @@
 //!
 //! - Writes are accumulated in a per-file buffer during editing
 //! - The buffer is flushed on `fsync()` (save) or `close()` (exit) operations
+//! - A flush that fails, like a script that doesn't compile, fails the `fsync` or the
+//!   clunk of `close` with its errno, and is logged
 //! - This ensures compatibility with standard text editors
 //!
 //! # Writable Files
@@ -65,28 +120,56 @@
 //! - **`/actors/{id}/state`**: Write JSON to update the actor's internal state.
 //!   The entire state is replaced with the written JSON object.
 //!
-//! - **`/actors/{id}/mailbox`**: Write a JSON message to add it to the actor's
+//! - **`/actors/{id}/mailbox`**: Write a JSON array of messages to replace the actor's
 //!   incoming message queue. Message format: `{"from":"sender_id","to":"actor_id","payload":{...}}`
 //!
 //! - **`/actors/{id}/paused`**: Write "true" or "false" to pause or unpause the actor.
+//!
+//! - **`/actors/{id}/inbox`**: Every line written is a JSON payload that is delivered
+//!   to the actor, or `{"from":"sender_id","payload":{...}}` to name a sender. Lines
+//!   are delivered as soon as they are complete, a malformed line fails its write.
+//!
+//! - **`/actors/{id}/script`**: Write a script to replace the script of the actor.
+//!   It has to compile, and its `migrate(state)` function, if it has one, turns the
+//!   current state into the new one. Otherwise the write fails with `EINVAL`, the
+//!   actor keeps running its old script, and `/actors/{id}/errors` tells why.
+//!   In a directory made with `mkdir /actors/{id}`, writing the script spawns the
+//!   actor `{id}` and runs its `init`. If that fails, the directory keeps why in its
+//!   `errors` and `diagnostics` files until a script that works is written. An actor
+//!   running a library script shows the source of its entry file, and writing the
+//!   file makes it run inline source instead.
+//!
+//! - **`/db/{name}/{key}`**: Write JSON to store it under the key, creating the key
+//!   if it doesn't exist yet. `rm` deletes the key. A `/` in a key shows up as `%2F`
+//!   in the file name and `%` as `%25`. Stores in subdirectories of `storage/`, like
+//!   the actor dbs, are found below `/db/{dir}/{name}`. The snapshots and journals
+//!   of the system are read-only, changing them fails with `EROFS`.
 
 #![allow(unused)]
 
 use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
+use nix::unistd::{Gid, Uid, User};
 use rs9p::{
-    Data, DirEntry, DirEntryData, FCall, GetAttrMask, QId, QIdType, Result, Stat, Time,
+    Data, DirEntry, DirEntryData, FCall, GetAttrMask, NONUNAME, QId, QIdType, Result, Stat, Time,
     error::errno::*,
     srv::{FId, Filesystem},
 };
 
-use crate::common::Message;
+use crate::common::{Db, Diagnostic, Message, Props, SNAPSHOT_DB, dirs::STORAGE, fnv1a};
 use stringlit::s;
 use tokio::sync::RwLock;
 
-use crate::system::System;
+use crate::groups::Group;
+use crate::journal::{self, JournalEvent};
+use crate::scripts::ScriptRef;
+use crate::system::{Checkpoint, EosError, Event, EventKind, Modified, System, check_id};
+use tokio::sync::broadcast::{self, error::RecvError};
 
 // Constants for dirent d_type field (matching Unix dirent.h)
 /// Directory type constant (DT_DIR from dirent.h)
@@ -105,6 +188,308 @@ const DIR_MODE: u32 = 0o755;
 /// Default permissions for files (rw-rw-r--)
 const FILE_MODE: u32 = 0o664;
 
+/// A node below `/history`
+enum HistoryNode<'a> {
+    /// `/history`
+    Root,
+    /// `/history/{tick}`
+    Tick,
+    /// `/history/{tick}/actors`
+    Actors(&'a Checkpoint),
+    /// `/history/{tick}/actors/{id}`
+    Actor(&'a serde_json::Value),
+    /// `/history/{tick}/actors/{id}/state`
+    State(&'a serde_json::Value),
+}
+
+/// Resolves `path` to a node below `/history`, `None` if it doesn't exist
+fn history_node<'a>(sys: &'a System, path: &str) -> Option<HistoryNode<'a>> {
+    let rest = path.strip_prefix("/history")?;
+    let parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let Some(tick) = parts.first() else {
+        return Some(HistoryNode::Root);
+    };
+    let checkpoint = sys.checkpoint_at(tick.parse().ok()?)?;
+    match parts[1..] {
+        [] => Some(HistoryNode::Tick),
+        ["actors"] => Some(HistoryNode::Actors(checkpoint)),
+        ["actors", id, ref rest @ ..] => {
+            let actor = checkpoint.actors()?.get(id)?;
+            match rest {
+                [] => Some(HistoryNode::Actor(actor)),
+                ["state"] => Some(HistoryNode::State(actor.get("state")?)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// A node below `/scripts`
+enum ScriptNode<'a> {
+    /// `/scripts`
+    Root,
+    /// `/scripts/{name}`, with the actors running the script
+    Script(ScriptRef<'a>, Vec<&'a str>),
+    /// `/scripts/{name}/source`
+    Source(ScriptRef<'a>),
+    /// `/scripts/{name}/files`
+    Files(ScriptRef<'a>),
+    /// `/scripts/{name}/actors`
+    Actors(Vec<&'a str>),
+}
+
+/// The scripts the actors are running by name, with the ids of the actors running them
+fn loaded_scripts(sys: &System) -> BTreeMap<String, (ScriptRef<'_>, Vec<&str>)> {
+    let mut scripts: BTreeMap<String, (ScriptRef, Vec<&str>)> = BTreeMap::new();
+    for actor in sys.actors.values() {
+        let script = actor.script_ref();
+        scripts
+            .entry(encode_key(&script.name()))
+            .or_insert_with(|| (script, Vec::new()))
+            .1
+            .push(&actor.id);
+    }
+    for (_, actors) in scripts.values_mut() {
+        actors.sort();
+    }
+    scripts
+}
+
+/// Resolves `path` to a node below `/scripts`, `None` if it doesn't exist
+fn script_node<'a>(sys: &'a System, path: &str) -> Option<ScriptNode<'a>> {
+    let rest = path.strip_prefix("/scripts")?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    let Some(name) = parts.first() else {
+        return Some(ScriptNode::Root);
+    };
+    let (script, actors) = loaded_scripts(sys).remove(*name)?;
+    match parts[1..] {
+        [] => Some(ScriptNode::Script(script, actors)),
+        ["source"] => Some(ScriptNode::Source(script)),
+        ["files"] => Some(ScriptNode::Files(script)),
+        ["actors"] => Some(ScriptNode::Actors(actors)),
+        _ => None,
+    }
+}
+
+/// The source of `script`, empty if the file of a library script can't be read
+fn script_source(script: ScriptRef) -> String {
+    script
+        .source()
+        .map(|source| source.into_owned())
+        .unwrap_or_else(|e| {
+            log::error!("Failed to read script {}: {}", script.name(), e);
+            String::new()
+        })
+}
+
+/// Compiler diagnostics as a JSON array
+fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    serde_json::to_string_pretty(diagnostics).unwrap_or_else(|_| s!("[]"))
+}
+
+/// One line per item, each ending in a newline
+fn format_lines(items: &[impl AsRef<str>]) -> String {
+    items
+        .iter()
+        .map(|item| format!("{}\n", item.as_ref()))
+        .collect()
+}
+
+/// A node below `/topics`
+enum TopicNode<'a> {
+    /// `/topics`
+    Root,
+    /// `/topics/{pattern}`, with the actors subscribed to it
+    Topic(Vec<&'a str>),
+    /// `/topics/{pattern}/subscribers`
+    Subscribers(Vec<&'a str>),
+}
+
+/// Resolves `path` to a node below `/topics`, `None` if nobody is subscribed to the pattern
+fn topic_node<'a>(sys: &'a System, path: &str) -> Option<TopicNode<'a>> {
+    let rest = path.strip_prefix("/topics")?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    let Some(pattern) = parts.first() else {
+        return Some(TopicNode::Root);
+    };
+    let subscribers = sys
+        .topics
+        .get(pattern)?
+        .iter()
+        .map(String::as_str)
+        .collect();
+    match parts[1..] {
+        [] => Some(TopicNode::Topic(subscribers)),
+        ["subscribers"] => Some(TopicNode::Subscribers(subscribers)),
+        _ => None,
+    }
+}
+
+/// File below `/db/{name}` holding the cache stats of the store
+const DB_STATS: &str = ".stats";
+
+/// A node below `/db`, backed by the files in `storage/`
+enum DbNode {
+    /// `/db` or a directory of stores below it, like `/db/actors`
+    Dir(PathBuf),
+    /// `/db/{name}`
+    Db(Db),
+    /// `/db/{name}/{key}`, the key doesn't have to exist
+    Key(Db, String),
+    /// `/db/{name}/.stats`
+    Stats(Db),
+}
+
+/// Resolves `path` to a node below `/db`, `None` if it doesn't exist
+fn db_node(path: &str) -> Option<DbNode> {
+    let rest = path.strip_prefix("/db")?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let mut dir = PathBuf::from(STORAGE);
+    let mut parts = rest.split('/').filter(|part| !part.is_empty());
+    while let Some(part) = parts.next() {
+        let path = dir.join(part);
+        if path.is_dir() {
+            dir = path;
+            continue;
+        }
+        if !path.is_file() {
+            return None;
+        }
+        let db = Db::new(&dir, part);
+        return match (parts.next(), parts.next()) {
+            (None, _) => Some(DbNode::Db(db)),
+            (Some(DB_STATS), None) => Some(DbNode::Stats(db)),
+            (Some(key), None) => Some(DbNode::Key(db, decode_key(key))),
+            _ => None,
+        };
+    }
+    Some(DbNode::Dir(dir))
+}
+
+/// Fails with `EROFS` if `db` is one the system keeps its own data in
+///
+/// These are the snapshots and the journals, which are told apart from other stores
+/// by the keys [`journal::Journal::start`] stores.
+fn check_writable(db: &Db) -> Result<()> {
+    let path = db.path();
+    let system_store = path == Path::new(STORAGE).join(SNAPSHOT_DB)
+        || (path.parent() == Some(Path::new(STORAGE))
+            && [journal::BASE, journal::HEAD]
+                .iter()
+                .all(|key| db.exists(key).unwrap_or(false)));
+    if system_store {
+        return Err(rs9p::Error::No(EROFS));
+    }
+    Ok(())
+}
+
+/// Turns a db key into a file name, which can't contain `/`
+fn encode_key(key: &str) -> String {
+    key.replace('%', "%25").replace('/', "%2F")
+}
+
+/// Reverses [`encode_key`]
+fn decode_key(name: &str) -> String {
+    name.replace("%2F", "/").replace("%25", "%")
+}
+
+/// Maps a failed db access to `EIO`
+fn db_error(e: anyhow::Error) -> rs9p::Error {
+    log::error!("Failed to access db: {}", e);
+    rs9p::Error::No(EIO)
+}
+
+/// A line written to an actor's `inbox` that carries a sender
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InboxLine {
+    from: Option<String>,
+    payload: serde_json::Value,
+}
+
+/// Parses a line written to the `inbox` of the actor `to`
+///
+/// A JSON object with just a `payload` and an optional `from` is sent as is,
+/// any other JSON value is the payload of a message without a sender.
+fn parse_inbox_line(to: &str, line: &str) -> serde_json::Result<Message> {
+    let value: serde_json::Value = serde_json::from_str(line)?;
+    let (from, payload) = match serde_json::from_value::<InboxLine>(value.clone()) {
+        Ok(InboxLine { from, payload }) => (from, payload),
+        Err(_) => (None, value),
+    };
+    Ok(Message {
+        from,
+        to: to.to_owned(),
+        payload,
+        ..Default::default()
+    })
+}
+
+/// Resolves the uid and gid of the user attaching as `uname`, or as the uid `n_uname`
+///
+/// Unknown users keep their uid if they sent one, anything else falls back to
+/// the user running the server.
+fn attaching_user(uname: &str, n_uname: u32) -> (u32, u32) {
+    let user = if n_uname == NONUNAME {
+        User::from_name(uname)
+    } else {
+        User::from_uid(Uid::from_raw(n_uname))
+    };
+    match user {
+        Ok(Some(user)) => (user.uid.as_raw(), user.gid.as_raw()),
+        _ if n_uname != NONUNAME => (n_uname, Gid::current().as_raw()),
+        _ => (Uid::current().as_raw(), Gid::current().as_raw()),
+    }
+}
+
+/// Converts a point in time to a 9P timestamp
+fn to_time(time: SystemTime) -> Time {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Time {
+        sec: since_epoch.as_secs(),
+        nsec: since_epoch.subsec_nanos() as u64,
+    }
+}
+
+/// When a file or directory below `storage/` was last written
+///
+/// The files don't count their changes, so the version is derived from the mtime. It
+/// mixes the seconds into the nanoseconds, as the nanoseconds alone repeat every second.
+fn storage_modified(path: &std::path::Path) -> Modified {
+    let time = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(UNIX_EPOCH);
+    Modified {
+        time,
+        version: time
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs() as u32 ^ since_epoch.subsec_nanos())
+            .unwrap_or_default(),
+    }
+}
+
+/// Joins the name of a directory entry to the path of its directory
+fn child_path(dir: &str, name: &str) -> String {
+    if dir == "/" || dir.is_empty() {
+        format!("/{}", name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
 /// 9P filesystem overlay that exposes System internals
 ///
 /// This structure wraps the actor system and implements the 9P filesystem
@@ -116,6 +501,17 @@ const FILE_MODE: u32 = 0o664;
 pub struct FsOverlay {
     /// The actor system being exposed, wrapped in Arc<RwLock> for thread-safe access
     pub sys: Arc<RwLock<System>>,
+    /// Directories made below `/actors` whose actors spawn once their script is written
+    pub new_actors: Arc<RwLock<BTreeMap<String, NewActor>>>,
+}
+
+/// A directory made with `mkdir /actors/{id}` that doesn't have an actor yet
+#[derive(Debug, Default)]
+pub struct NewActor {
+    /// why the last script written to it didn't spawn the actor
+    pub errors: String,
+    /// what the compiler found in that script
+    pub diagnostics: Vec<Diagnostic>,
 }
 
 impl FsOverlay {
@@ -125,7 +521,10 @@ impl FsOverlay {
     ///
     /// * `sys` - The actor system to expose through the filesystem
     pub fn new(sys: Arc<RwLock<System>>) -> Self {
-        Self { sys }
+        Self {
+            sys,
+            new_actors: Default::default(),
+        }
     }
 }
 
@@ -141,6 +540,125 @@ pub struct MyFId {
     pub is_dir: RwLock<bool>,
     /// Write buffer for accumulating writes before commit
     pub write_buffer: RwLock<Option<Vec<u8>>>,
+    /// Events of an open `events` file
+    pub events: RwLock<Option<EventStream>>,
+    /// uid and gid of the user that attached, every file belongs to them
+    pub owner: RwLock<(u32, u32)>,
+}
+
+/// A node below `/groups`
+enum GroupNode<'a> {
+    /// `/groups`
+    Root,
+    /// `/groups/{id}`
+    Group(&'a Group),
+    /// `/groups/{id}/members`
+    Members(&'a Group),
+    /// `/groups/{id}/routing`
+    Routing(&'a Group),
+}
+
+/// Resolves `path` to a node below `/groups`, `None` if it doesn't exist
+fn group_node<'a>(sys: &'a System, path: &str) -> Option<GroupNode<'a>> {
+    let rest = path.strip_prefix("/groups")?;
+    if !rest.is_empty() && !rest.starts_with('/') {
+        return None;
+    }
+    let parts: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    let Some(id) = parts.first() else {
+        return Some(GroupNode::Root);
+    };
+    let group = sys.groups.get(*id)?;
+    match parts[1..] {
+        [] => Some(GroupNode::Group(group)),
+        ["members"] => Some(GroupNode::Members(group)),
+        ["routing"] => Some(GroupNode::Routing(group)),
+        _ => None,
+    }
+}
+
+/// The routing of `group` by its name, followed by the hash key if it has one
+fn format_routing(group: &Group) -> String {
+    let routing = serde_json::to_value(group.routing)
+        .ok()
+        .and_then(|routing| routing.as_str().map(str::to_owned))
+        .unwrap_or_default();
+    match &group.hash_key {
+        Some(key) => format!("{routing} {key}\n"),
+        None => format!("{routing}\n"),
+    }
+}
+
+/// How long a read of an `events` file waits for an event before it returns a heartbeat
+pub const EVENTS_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// The events an open `events` file receives, from the time it was opened
+#[derive(Debug)]
+pub struct EventStream {
+    receiver: broadcast::Receiver<Event>,
+    /// only events about this actor, or all events if `None`
+    actor: Option<String>,
+    /// received events that didn't fit into the last read
+    unread: Vec<u8>,
+}
+
+impl EventStream {
+    /// Waits for the next events, returning at most `count` bytes
+    ///
+    /// Events of the same tick usually arrive together, so once one arrived, all
+    /// events that are already waiting are taken as well. Returns a `heartbeat` line
+    /// if nothing arrived within [`EVENTS_HEARTBEAT`].
+    async fn read(&mut self, count: u32) -> Vec<u8> {
+        if self.unread.is_empty() {
+            let deadline = tokio::time::Instant::now() + EVENTS_HEARTBEAT;
+            while self.unread.is_empty() {
+                match tokio::time::timeout_at(deadline, self.receiver.recv()).await {
+                    Ok(Err(RecvError::Closed)) => break,
+                    Ok(received) => self.push(received),
+                    Err(_) => {
+                        self.unread.extend_from_slice(b"{\"kind\":\"heartbeat\"}\n");
+                    }
+                }
+            }
+            while let Ok(event) = self.receiver.try_recv() {
+                self.push(Ok(event));
+            }
+        }
+        let end = self.unread.len().min(count as usize);
+        self.unread.drain(..end).collect()
+    }
+
+    /// Appends a received event as a line of JSON, if it is one the stream shows
+    fn push(&mut self, received: std::result::Result<Event, RecvError>) {
+        let line = match received {
+            Ok(event) => {
+                let shown = match &self.actor {
+                    None => true,
+                    Some(actor) => event.actor() == Some(actor.as_str()),
+                };
+                if !shown {
+                    return;
+                }
+                serde_json::to_string(&event).unwrap_or_default()
+            }
+            Err(RecvError::Lagged(missed)) => {
+                serde_json::json!({ "kind": "lagged", "missed": missed }).to_string()
+            }
+            // the system outlives every fid
+            Err(RecvError::Closed) => return,
+        };
+        self.unread.extend_from_slice(line.as_bytes());
+        self.unread.push(b'\n');
+    }
+}
+
+/// Returns `None` if `path` is not an `events` file, or the actor the events are about
+fn events_filter(path: &str) -> Option<Option<String>> {
+    if path == "/events" {
+        return Some(None);
+    }
+    let id = path.strip_prefix("/actors/")?.strip_suffix("/events")?;
+    (!id.contains('/')).then(|| Some(id.to_owned()))
 }
 
 impl MyFId {
@@ -155,6 +673,8 @@ impl MyFId {
             path: RwLock::new(path),
             is_dir: RwLock::new(is_dir),
             write_buffer: RwLock::new(None),
+            events: RwLock::new(None),
+            owner: RwLock::new((0, 0)),
         }
     }
 
@@ -181,13 +701,11 @@ impl Filesystem for FsOverlay {
         // Initialize the fid with root directory information
         *fid.aux.path.write().await = "/".to_string();
         *fid.aux.is_dir.write().await = true;
+        *fid.aux.owner.write().await = attaching_user(uname, n_uname);
 
+        let sys = self.sys.read().await;
         Ok(FCall::RAttach {
-            qid: QId {
-                typ: QIdType::DIR,
-                version: 1,
-                path: 1,
-            },
+            qid: self.qid(&sys, "/", true),
         })
     }
 
@@ -205,8 +723,9 @@ impl Filesystem for FsOverlay {
     /// Files are read-only since the filesystem provides inspection capabilities only.
     ///
     /// ## Timestamps
-    /// All timestamps (atime, mtime, ctime) are set to the current system time,
-    /// reflecting that the content is dynamically generated.
+    /// All timestamps (atime, mtime, ctime) are set to the time the node last changed,
+    /// as tracked by the System. The QID version counts these changes, so caching
+    /// clients and tools like `make` notice when content changes.
     ///
     /// ## Link Counts
     /// - Root directory: 3 (., .., and actors/)
@@ -215,7 +734,7 @@ impl Filesystem for FsOverlay {
     /// - Files: 1
     ///
     /// ## Ownership
-    /// All files are owned by uid/gid 1000 (typical first user on Linux systems).
+    /// All files are owned by the uid/gid of the user that attached.
     ///
     /// # Arguments
     ///
@@ -246,14 +765,8 @@ impl Filesystem for FsOverlay {
             return Err(rs9p::Error::No(ENOENT));
         }
 
-        // Get current time for timestamps
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default();
-        let time = Time {
-            sec: now.as_secs(),
-            nsec: now.subsec_nanos() as u64,
-        };
+        let time = to_time(self.modified(&sys, path_str).time);
+        let (uid, gid) = *fid.aux.owner.read().await;
 
         // Determine QID type and mode
         let (qid_type, mode_type, base_perms) = if is_directory {
@@ -287,13 +800,13 @@ impl Filesystem for FsOverlay {
         // Build the stat structure
         let stat = Stat {
             mode,
-            uid: 1000,
-            gid: 1000,
+            uid,
+            gid,
             nlink,
             rdev: 0,
             size,
             blksize: 4096,
-            blocks: (size + 511) / 512, // 512-byte blocks as per stat(2)
+            blocks: size.div_ceil(512), // 512-byte blocks as per stat(2)
             atime: time,
             mtime: time,
             ctime: time,
@@ -313,11 +826,7 @@ impl Filesystem for FsOverlay {
 
         Ok(FCall::RGetAttr {
             valid,
-            qid: QId {
-                typ: qid_type,
-                version: 1,
-                path: self.path_to_qid(path_str),
-            },
+            qid: self.qid(&sys, path_str, is_directory),
             stat,
         })
     }
@@ -331,7 +840,10 @@ impl Filesystem for FsOverlay {
 
             // Get write lock on system and write the buffered data
             let mut sys = self.sys.write().await;
-            let _ = self.write_file(&mut sys, &path, &buffer).await;
+            if let Err(e) = self.write_file(&mut sys, &path, &buffer).await {
+                log::error!("rclunk: failed to write {}: {}", path, e);
+                return Err(e);
+            }
         }
 
         Ok(FCall::RClunk)
@@ -344,12 +856,13 @@ impl Filesystem for FsOverlay {
         wnames: &[String],
     ) -> Result<FCall> {
         let current_path = fid.aux.path.read().await.clone();
-        let current_path = if current_path == "" {
+        let current_path = if current_path.is_empty() {
             s!("/")
         } else {
             current_path
         };
         let current_is_dir = *fid.aux.is_dir.read().await;
+        *newfid.aux.owner.write().await = *fid.aux.owner.read().await;
         let sys = self.sys.read().await;
 
         log::debug!("rwalk: current_path={}, wnames={:?}", current_path, wnames);
@@ -371,7 +884,11 @@ impl Filesystem for FsOverlay {
             if wname == ".." {
                 // Go up one directory
                 if path != "/" {
-                    path = path.rsplitn(2, '/').nth(1).unwrap_or("/").to_string();
+                    path = path
+                        .rsplit_once('/')
+                        .map(|(parent, _)| parent)
+                        .unwrap_or("/")
+                        .to_string();
                     if path.is_empty() {
                         path = "/".to_string();
                     }
@@ -391,13 +908,7 @@ impl Filesystem for FsOverlay {
             }
 
             final_is_dir = is_dir;
-            let qid_type = if is_dir { QIdType::DIR } else { QIdType::FILE };
-
-            wqids.push(QId {
-                typ: qid_type,
-                version: 1,
-                path: self.path_to_qid(&path),
-            });
+            wqids.push(self.qid(&sys, &path, is_dir));
         }
 
         // Update newfid with the final path after successful walk
@@ -438,6 +949,18 @@ impl Filesystem for FsOverlay {
             count
         );
 
+        if let Some(actor) = events_filter(&path) {
+            // Wait for events without holding the system lock, or nothing can happen
+            let mut events = fid.aux.events.write().await;
+            let stream = match &mut *events {
+                Some(stream) => stream,
+                None => events.insert(self.subscribe(actor).await),
+            };
+            return Ok(FCall::RRead {
+                data: Data(stream.read(count).await),
+            });
+        }
+
         let sys = self.sys.read().await;
 
         if is_dir {
@@ -475,6 +998,13 @@ impl Filesystem for FsOverlay {
         if is_dir {
             return Err(rs9p::Error::No(EISDIR));
         }
+        if let Some(DbNode::Key(db, _)) = db_node(&path) {
+            check_writable(&db)?;
+        }
+
+        if path.starts_with("/actors/") && path.ends_with("/inbox") {
+            return self.write_inbox(fid, &path, data).await;
+        }
 
         // Get or create write buffer
         let mut write_buffer = fid.aux.write_buffer.write().await;
@@ -511,21 +1041,15 @@ impl Filesystem for FsOverlay {
     }
 
     async fn rlopen(&self, fid: &FId<Self::FId>, _flags: u32) -> Result<FCall> {
-        let qid_type = if *fid.aux.is_dir.read().await {
-            QIdType::DIR
-        } else {
-            QIdType::FILE
-        };
-
+        let is_dir = *fid.aux.is_dir.read().await;
         let path = fid.aux.path.read().await.clone();
-        Ok(FCall::RlOpen {
-            qid: QId {
-                typ: qid_type,
-                version: 1,
-                path: self.path_to_qid(&path),
-            },
-            iounit: 8192,
-        })
+        let qid = self.qid(&*self.sys.read().await, &path, is_dir);
+
+        if let Some(actor) = events_filter(&path) {
+            // Subscribe right away, so no event between opening and reading is missed
+            *fid.aux.events.write().await = Some(self.subscribe(actor).await);
+        }
+        Ok(FCall::RlOpen { qid, iounit: 8192 })
     }
 
     async fn rfsync(&self, fid: &FId<Self::FId>) -> Result<FCall> {
@@ -539,7 +1063,10 @@ impl Filesystem for FsOverlay {
 
             // Get write lock on system and write the buffered data
             let mut sys = self.sys.write().await;
-            self.write_file(&mut sys, &path, &buffer).await?;
+            if let Err(e) = self.write_file(&mut sys, &path, &buffer).await {
+                log::error!("rfsync: failed to write {}: {}", path, e);
+                return Err(e);
+            }
         }
 
         Ok(FCall::RFSync)
@@ -558,6 +1085,87 @@ impl Filesystem for FsOverlay {
         Ok(FCall::RSetAttr)
     }
 
+    /// Create a file in a directory (9P2000.L lcreate operation)
+    ///
+    /// Only keys of a db can be created. The new key starts out as `null`, and the
+    /// fid is moved to the new file, ready for the write that follows.
+    async fn rlcreate(
+        &self,
+        fid: &FId<Self::FId>,
+        name: &str,
+        _flags: u32,
+        _mode: u32,
+        _gid: u32,
+    ) -> Result<FCall> {
+        let dir = fid.aux.path.read().await.clone();
+        let path = child_path(&dir, name);
+        log::debug!("rlcreate: path={}", path);
+
+        let mut sys = self.sys.write().await;
+        self.create_file(&mut sys, &path).await?;
+
+        *fid.aux.path.write().await = path.clone();
+        *fid.aux.is_dir.write().await = false;
+        Ok(FCall::RlCreate {
+            qid: self.qid(&sys, &path, false),
+            iounit: 8192,
+        })
+    }
+
+    /// Create a directory (9P2000.L mkdir operation)
+    ///
+    /// Only actor directories can be made. The actor is spawned as soon as a
+    /// script is written to `/actors/{id}/script`, until then the directory is empty.
+    async fn rmkdir(
+        &self,
+        fid: &FId<Self::FId>,
+        name: &str,
+        _mode: u32,
+        _gid: u32,
+    ) -> Result<FCall> {
+        let dir = fid.aux.path.read().await.clone();
+        let path = child_path(&dir, name);
+        log::debug!("rmkdir: path={}", path);
+
+        if dir != "/actors" {
+            return Err(rs9p::Error::No(EACCES));
+        }
+        if let Err(e) = check_id(name) {
+            log::error!("Can't make {}: {}", path, e);
+            return Err(rs9p::Error::No(EINVAL));
+        }
+        let sys = self.sys.read().await;
+        let mut new_actors = self.new_actors.write().await;
+        if sys.actors.contains_key(name) || new_actors.contains_key(name) {
+            return Err(rs9p::Error::No(EEXIST));
+        }
+        new_actors.insert(name.to_owned(), NewActor::default());
+        log::info!("Waiting for the script of new actor {}", name);
+
+        Ok(FCall::RMkDir {
+            qid: self.qid(&sys, &path, true),
+        })
+    }
+
+    async fn rremove(&self, fid: &FId<Self::FId>) -> Result<FCall> {
+        let path = fid.aux.path.read().await.clone();
+        log::debug!("rremove: path={}", path);
+
+        let mut sys = self.sys.write().await;
+        self.remove_path(&mut sys, &path).await?;
+        Ok(FCall::RRemove)
+    }
+
+    async fn runlinkat(&self, dirfid: &FId<Self::FId>, name: &str, _flags: u32) -> Result<FCall> {
+        let dir = dirfid.aux.path.read().await.clone();
+        let path = child_path(&dir, name);
+        log::debug!("runlinkat: path={}", path);
+
+        let mut sys = self.sys.write().await;
+        self.remove_path(&mut sys, &path).await?;
+        Ok(FCall::RUnlinkAt)
+    }
+
     async fn rreaddir(&self, fid: &FId<Self::FId>, offset: u64, count: u32) -> Result<FCall> {
         let path = fid.aux.path.read().await.clone();
         let mut is_dir = *fid.aux.is_dir.read().await;
@@ -606,11 +1214,7 @@ impl Filesystem for FsOverlay {
             // Determine the d_type value using Unix dirent constants
             let typ = if *is_dir { DT_DIR } else { DT_REG };
 
-            let qid = QId {
-                typ: if *is_dir { QIdType::DIR } else { QIdType::FILE },
-                version: 1,
-                path: self.path_to_qid(&entry_path),
-            };
+            let qid = self.qid(&sys, &entry_path, *is_dir);
 
             let entry = DirEntry {
                 qid,
@@ -649,7 +1253,7 @@ impl FsOverlay {
     /// - Non-existent paths (returns error)
     /// - Dynamic content size calculation
     /// - Proper file type determination
-    /// - Current timestamp generation
+    /// - Modification time lookup
     /// - Correct link count calculation
     ///
     /// # File Type Modes
@@ -676,7 +1280,7 @@ impl FsOverlay {
     /// ```
     ///
     /// This differs from the `blksize` field which is the preferred I/O block size (4096).
-
+    ///
     /// Read directory entries using the `readdir` operation (9P2000.L)
     ///
     /// This is the preferred method for reading directories in 9P2000.L,
@@ -704,18 +1308,78 @@ impl FsOverlay {
     /// # Errors
     ///
     /// Returns `ENOTDIR` if the fid does not refer to a directory.
-
+    ///
     /// Convert a filesystem path to a QID path value
     ///
-    /// QIDs uniquely identify files in 9P. We use a hash of the path string.
+    /// QIDs uniquely identify files in 9P. We use the 64 bit FNV-1a hash of the path
+    /// string, which unlike `DefaultHasher` is the same across builds and restarts.
     fn path_to_qid(&self, path: &str) -> u64 {
         let path = if path.is_empty() { "/" } else { path };
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+        fnv1a(path.bytes())
+    }
 
-        let mut hasher = DefaultHasher::new();
-        path.hash(&mut hasher);
-        hasher.finish()
+    /// Build the QID of a path, its version counts the changes of the node
+    fn qid(&self, sys: &System, path: &str, is_dir: bool) -> QId {
+        QId {
+            typ: if is_dir { QIdType::DIR } else { QIdType::FILE },
+            version: self.modified(sys, path).version,
+            path: self.path_to_qid(path),
+        }
+    }
+
+    /// Find out when the node at a path last changed
+    ///
+    /// Nodes the system doesn't track report the time the system started.
+    fn modified(&self, sys: &System, path: &str) -> Modified {
+        match path {
+            "/actors" => sys.changes.actors,
+            "/spawn_queue" => sys.changes.spawn_queue,
+            "/dead_letters" => sys.changes.dead_letters,
+            "/history" => sys.changes.history,
+            // a stream changes all the time
+            "/events" => Modified::default(),
+            _ if path.starts_with("/history/") => path
+                .split('/')
+                .nth(2)
+                .and_then(|tick| sys.checkpoint_at(tick.parse().ok()?))
+                .map(|checkpoint| Modified {
+                    time: checkpoint.time,
+                    version: 0,
+                })
+                .unwrap_or(sys.changes.history),
+            // which scripts are loaded changes with the actors running them
+            _ if path.starts_with("/scripts") => sys.changes.actors,
+            _ if path.starts_with("/topics") => sys.changes.topics,
+            _ if path.starts_with("/groups") => sys.changes.groups,
+            _ if path.starts_with("/db") => match db_node(path) {
+                Some(DbNode::Dir(dir)) => storage_modified(&dir),
+                Some(DbNode::Db(db) | DbNode::Key(db, _) | DbNode::Stats(db)) => {
+                    storage_modified(&db.path())
+                }
+                None => sys.changes.started,
+            },
+            _ if path.starts_with("/actors/") => {
+                let mut parts = path.trim_start_matches("/actors/").split('/');
+                let Some(actor) = parts.next().and_then(|id| sys.actors.get(id)) else {
+                    // a directory made with mkdir, still waiting for its script
+                    return sys.changes.actors;
+                };
+                let changes = &actor.changes;
+                match parts.next() {
+                    Some("state") => changes.state,
+                    Some("mailbox") => changes.mailbox,
+                    Some("script") => changes.script,
+                    Some("paused") => changes.paused,
+                    Some("error") => changes.error,
+                    Some("errors") => changes.script_errors,
+                    Some("diagnostics") => changes.diagnostics,
+                    Some("timers") => changes.timers,
+                    Some("events") => Modified::default(),
+                    _ => changes.spawned,
+                }
+            }
+            _ => sys.changes.started,
+        }
     }
 
     /// Get information about a path in the virtual filesystem
@@ -738,6 +1402,60 @@ impl FsOverlay {
                 let content = self.format_spawn_queue(sys);
                 Ok((true, false, content.len() as u64))
             }
+            "/dead_letters" => {
+                let content = self.format_dead_letters(sys);
+                Ok((true, false, content.len() as u64))
+            }
+            "/events" => Ok((true, false, 0)),
+            _ if path.starts_with("/history") => Ok(match history_node(sys, path) {
+                Some(HistoryNode::State(state)) => {
+                    let content = serde_json::to_string_pretty(state).unwrap_or_default();
+                    (true, false, content.len() as u64)
+                }
+                Some(_) => (true, true, 0),
+                None => (false, false, 0),
+            }),
+            _ if path.starts_with("/db") => Ok(match db_node(path) {
+                Some(DbNode::Dir(_) | DbNode::Db(_)) => (true, true, 0),
+                Some(DbNode::Key(db, key)) => match self.format_db_value(&db, &key)? {
+                    Some(content) => (true, false, content.len() as u64),
+                    None => (false, false, 0),
+                },
+                Some(DbNode::Stats(db)) => (true, false, self.format_db_stats(&db).len() as u64),
+                None => (false, false, 0),
+            }),
+            _ if path.starts_with("/scripts") => Ok(match script_node(sys, path) {
+                Some(ScriptNode::Root | ScriptNode::Script(..)) => (true, true, 0),
+                Some(ScriptNode::Source(script)) => {
+                    (true, false, script_source(script).len() as u64)
+                }
+                Some(ScriptNode::Files(script)) => (
+                    true,
+                    false,
+                    self.format_script_files(sys, script).len() as u64,
+                ),
+                Some(ScriptNode::Actors(actors)) => {
+                    (true, false, format_lines(&actors).len() as u64)
+                }
+                None => (false, false, 0),
+            }),
+            _ if path.starts_with("/groups") => Ok(match group_node(sys, path) {
+                Some(GroupNode::Root | GroupNode::Group(_)) => (true, true, 0),
+                Some(GroupNode::Members(group)) => {
+                    (true, false, format_lines(&group.members).len() as u64)
+                }
+                Some(GroupNode::Routing(group)) => {
+                    (true, false, format_routing(group).len() as u64)
+                }
+                None => (false, false, 0),
+            }),
+            _ if path.starts_with("/topics") => Ok(match topic_node(sys, path) {
+                Some(TopicNode::Root | TopicNode::Topic(_)) => (true, true, 0),
+                Some(TopicNode::Subscribers(actors)) => {
+                    (true, false, format_lines(&actors).len() as u64)
+                }
+                None => (false, false, 0),
+            }),
             _ => {
                 // Check if it's an actor path
                 if path.starts_with("/actors/") {
@@ -748,7 +1466,19 @@ impl FsOverlay {
 
                     let actor_id = parts[0];
                     if !sys.actors.contains_key(actor_id) {
-                        return Ok((false, false, 0));
+                        let new_actors = self.new_actors.read().await;
+                        let Some(new_actor) = new_actors.get(actor_id) else {
+                            return Ok((false, false, 0));
+                        };
+                        return Ok(match parts[1..] {
+                            [] => (true, true, 0),
+                            ["errors"] => (true, false, new_actor.errors.len() as u64),
+                            ["diagnostics"] => {
+                                let content = format_diagnostics(&new_actor.diagnostics);
+                                (true, false, content.len() as u64)
+                            }
+                            _ => (false, false, 0),
+                        });
                     }
 
                     if parts.len() == 1 {
@@ -762,7 +1492,10 @@ impl FsOverlay {
                                 let content = self.format_mailbox(actor);
                                 Ok((true, false, content.len() as u64))
                             }
-                            "script" => Ok((true, false, actor.script.len() as u64)),
+                            "script" => {
+                                let content = script_source(actor.script_ref());
+                                Ok((true, false, content.len() as u64))
+                            }
                             "state" => {
                                 let content =
                                     serde_json::to_string_pretty(&actor.state).unwrap_or_default();
@@ -772,6 +1505,20 @@ impl FsOverlay {
                                 let content = actor.paused.to_string();
                                 Ok((true, false, content.len() as u64))
                             }
+                            "inbox" | "events" => Ok((true, false, 0)),
+                            "error" => {
+                                let content = self.format_error(actor);
+                                Ok((true, false, content.len() as u64))
+                            }
+                            "errors" => Ok((true, false, actor.script_errors.len() as u64)),
+                            "diagnostics" => {
+                                let content = self.format_actor_diagnostics(sys, actor);
+                                Ok((true, false, content.len() as u64))
+                            }
+                            "timers" => {
+                                let content = self.format_timers(sys, actor);
+                                Ok((true, false, content.len() as u64))
+                            }
                             _ => Ok((false, false, 0)),
                         }
                     } else {
@@ -803,21 +1550,158 @@ impl FsOverlay {
                     false,
                     self.format_spawn_queue(sys).len() as u64,
                 ),
+                (
+                    "dead_letters".to_string(),
+                    false,
+                    self.format_dead_letters(sys).len() as u64,
+                ),
+                ("history".to_string(), true, 0),
+                ("db".to_string(), true, 0),
+                ("scripts".to_string(), true, 0),
+                ("topics".to_string(), true, 0),
+                ("groups".to_string(), true, 0),
+                ("events".to_string(), false, 0),
             ],
             "/actors" => {
+                let new_actors = self.new_actors.read().await;
                 let mut entries: Vec<_> = sys
                     .actors
                     .keys()
+                    .chain(new_actors.keys())
                     .map(|id| (id.clone(), true, 0u64))
                     .collect();
                 entries.sort_by(|a, b| a.0.cmp(&b.0));
                 entries
             }
+            _ if path.starts_with("/history") => match history_node(sys, path) {
+                Some(HistoryNode::Root) => sys
+                    .history
+                    .iter()
+                    .map(|checkpoint| (checkpoint.tick.to_string(), true, 0))
+                    .collect(),
+                Some(HistoryNode::Tick) => vec![("actors".to_string(), true, 0)],
+                Some(HistoryNode::Actors(checkpoint)) => {
+                    let mut entries: Vec<_> = checkpoint
+                        .actors()
+                        .into_iter()
+                        .flat_map(|actors| actors.keys())
+                        .map(|id| (id.clone(), true, 0u64))
+                        .collect();
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                    entries
+                }
+                Some(HistoryNode::Actor(actor)) => vec![(
+                    "state".to_string(),
+                    false,
+                    serde_json::to_string_pretty(&actor["state"])
+                        .unwrap_or_default()
+                        .len() as u64,
+                )],
+                _ => vec![],
+            },
+            _ if path.starts_with("/db") => match db_node(path) {
+                Some(DbNode::Dir(dir)) => {
+                    let mut entries: Vec<_> = std::fs::read_dir(dir)
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .filter_map(|entry| Some((entry.file_name().into_string().ok()?, true, 0)))
+                        .collect();
+                    entries.sort_by(|a, b| a.0.cmp(&b.0));
+                    entries
+                }
+                Some(DbNode::Db(db)) => {
+                    let mut entries = vec![(
+                        DB_STATS.to_string(),
+                        false,
+                        self.format_db_stats(&db).len() as u64,
+                    )];
+                    let values = db.range::<serde_json::Value>(..).map_err(db_error)?;
+                    entries.extend(values.into_iter().map(|(key, value)| {
+                        (
+                            encode_key(&key),
+                            false,
+                            serde_json::to_string_pretty(&value)
+                                .unwrap_or_default()
+                                .len() as u64,
+                        )
+                    }));
+                    entries
+                }
+                _ => vec![],
+            },
+            _ if path.starts_with("/scripts") => match script_node(sys, path) {
+                Some(ScriptNode::Root) => loaded_scripts(sys)
+                    .into_keys()
+                    .map(|name| (name, true, 0))
+                    .collect(),
+                Some(ScriptNode::Script(script, actors)) => {
+                    vec![
+                        (
+                            "source".to_string(),
+                            false,
+                            script_source(script).len() as u64,
+                        ),
+                        (
+                            "files".to_string(),
+                            false,
+                            self.format_script_files(sys, script).len() as u64,
+                        ),
+                        (
+                            "actors".to_string(),
+                            false,
+                            format_lines(&actors).len() as u64,
+                        ),
+                    ]
+                }
+                _ => vec![],
+            },
+            _ if path.starts_with("/groups") => match group_node(sys, path) {
+                Some(GroupNode::Root) => {
+                    sys.groups.keys().map(|id| (id.clone(), true, 0)).collect()
+                }
+                Some(GroupNode::Group(group)) => vec![
+                    (
+                        "members".to_string(),
+                        false,
+                        format_lines(&group.members).len() as u64,
+                    ),
+                    (
+                        "routing".to_string(),
+                        false,
+                        format_routing(group).len() as u64,
+                    ),
+                ],
+                _ => vec![],
+            },
+            _ if path.starts_with("/topics") => match topic_node(sys, path) {
+                Some(TopicNode::Root) => sys
+                    .topics
+                    .patterns()
+                    .map(|pattern| (pattern.to_string(), true, 0))
+                    .collect(),
+                Some(TopicNode::Topic(actors)) => vec![(
+                    "subscribers".to_string(),
+                    false,
+                    format_lines(&actors).len() as u64,
+                )],
+                _ => vec![],
+            },
             _ => {
                 if path.starts_with("/actors/") {
                     let parts: Vec<&str> = path.trim_start_matches("/actors/").split('/').collect();
                     if parts.len() == 1 && !parts[0].is_empty() {
                         let actor_id = parts[0];
+                        if let Some(new_actor) = self.new_actors.read().await.get(actor_id) {
+                            return Ok(vec![
+                                ("errors".to_string(), false, new_actor.errors.len() as u64),
+                                (
+                                    "diagnostics".to_string(),
+                                    false,
+                                    format_diagnostics(&new_actor.diagnostics).len() as u64,
+                                ),
+                            ]);
+                        }
                         if let Some(actor) = sys.actors.get(actor_id) {
                             return Ok(vec![
                                 (
@@ -825,7 +1709,11 @@ impl FsOverlay {
                                     false,
                                     self.format_mailbox(actor).len() as u64,
                                 ),
-                                ("script".to_string(), false, actor.script.len() as u64),
+                                (
+                                    "script".to_string(),
+                                    false,
+                                    script_source(actor.script_ref()).len() as u64,
+                                ),
                                 (
                                     "state".to_string(),
                                     false,
@@ -838,6 +1726,28 @@ impl FsOverlay {
                                     false,
                                     actor.paused.to_string().len() as u64,
                                 ),
+                                ("inbox".to_string(), false, 0),
+                                ("events".to_string(), false, 0),
+                                (
+                                    "error".to_string(),
+                                    false,
+                                    self.format_error(actor).len() as u64,
+                                ),
+                                (
+                                    "errors".to_string(),
+                                    false,
+                                    actor.script_errors.len() as u64,
+                                ),
+                                (
+                                    "diagnostics".to_string(),
+                                    false,
+                                    self.format_actor_diagnostics(sys, actor).len() as u64,
+                                ),
+                                (
+                                    "timers".to_string(),
+                                    false,
+                                    self.format_timers(sys, actor).len() as u64,
+                                ),
                             ]);
                         }
                     }
@@ -916,16 +1826,64 @@ impl FsOverlay {
     async fn read_file(&self, sys: &System, path: &str) -> Result<Vec<u8>> {
         match path {
             "/spawn_queue" => Ok(self.format_spawn_queue(sys).into_bytes()),
+            "/dead_letters" => Ok(self.format_dead_letters(sys).into_bytes()),
+            _ if path.starts_with("/history") => match history_node(sys, path) {
+                Some(HistoryNode::State(state)) => Ok(serde_json::to_string_pretty(state)
+                    .unwrap_or_default()
+                    .into_bytes()),
+                _ => Ok(vec![]),
+            },
+            _ if path.starts_with("/db") => match db_node(path) {
+                Some(DbNode::Key(db, key)) => Ok(self
+                    .format_db_value(&db, &key)?
+                    .ok_or(rs9p::Error::No(ENOENT))?
+                    .into_bytes()),
+                Some(DbNode::Stats(db)) => Ok(self.format_db_stats(&db).into_bytes()),
+                _ => Ok(vec![]),
+            },
+            _ if path.starts_with("/scripts") => match script_node(sys, path) {
+                Some(ScriptNode::Source(script)) => Ok(script
+                    .source()
+                    .map_err(|e| {
+                        log::error!("Failed to read script {}: {}", script.name(), e);
+                        rs9p::Error::No(EIO)
+                    })?
+                    .into_owned()
+                    .into_bytes()),
+                Some(ScriptNode::Files(script)) => {
+                    Ok(self.format_script_files(sys, script).into_bytes())
+                }
+                Some(ScriptNode::Actors(actors)) => Ok(format_lines(&actors).into_bytes()),
+                _ => Ok(vec![]),
+            },
+            _ if path.starts_with("/groups") => match group_node(sys, path) {
+                Some(GroupNode::Members(group)) => Ok(format_lines(&group.members).into_bytes()),
+                Some(GroupNode::Routing(group)) => Ok(format_routing(group).into_bytes()),
+                _ => Ok(vec![]),
+            },
+            _ if path.starts_with("/topics") => match topic_node(sys, path) {
+                Some(TopicNode::Subscribers(actors)) => Ok(format_lines(&actors).into_bytes()),
+                _ => Ok(vec![]),
+            },
             _ => {
                 if path.starts_with("/actors/") {
                     let parts: Vec<&str> = path.trim_start_matches("/actors/").split('/').collect();
                     if parts.len() >= 2 {
                         let actor_id = parts[0];
+                        if let Some(new_actor) = self.new_actors.read().await.get(actor_id) {
+                            return match parts[1] {
+                                "errors" => Ok(new_actor.errors.clone().into_bytes()),
+                                "diagnostics" => {
+                                    Ok(format_diagnostics(&new_actor.diagnostics).into_bytes())
+                                }
+                                _ => Err(rs9p::Error::No(ENOENT)),
+                            };
+                        }
                         if let Some(actor) = sys.actors.get(actor_id) {
                             match parts[1] {
                                 "mailbox" => return Ok(self.format_mailbox(actor).into_bytes()),
                                 "script" => {
-                                    return Ok(actor.script.clone().into_bytes());
+                                    return Ok(script_source(actor.script_ref()).into_bytes());
                                 }
                                 "state" => {
                                     return Ok(serde_json::to_string_pretty(&actor.state)
@@ -935,6 +1893,20 @@ impl FsOverlay {
                                 "paused" => {
                                     return Ok(actor.paused.to_string().into_bytes());
                                 }
+                                "error" => {
+                                    return Ok(self.format_error(actor).into_bytes());
+                                }
+                                "errors" => {
+                                    return Ok(actor.script_errors.clone().into_bytes());
+                                }
+                                "diagnostics" => {
+                                    return Ok(self
+                                        .format_actor_diagnostics(sys, actor)
+                                        .into_bytes());
+                                }
+                                "timers" => {
+                                    return Ok(self.format_timers(sys, actor).into_bytes());
+                                }
                                 _ => {}
                             }
                         }
@@ -956,63 +1928,250 @@ impl FsOverlay {
         // Parse the data as a string
         let content = std::str::from_utf8(data).map_err(|_| rs9p::Error::No(EINVAL))?;
 
-        match path {
-            _ => {
-                if path.starts_with("/actors/") {
-                    let parts: Vec<&str> = path.trim_start_matches("/actors/").split('/').collect();
-                    if parts.len() >= 2 {
-                        let actor_id = parts[0];
-                        if let Some(actor) = sys.actors.get_mut(actor_id) {
-                            match parts[1] {
-                                "state" => {
-                                    // Parse and update actor state
-                                    let new_state: serde_json::Value =
-                                        serde_json::from_str(content).map_err(|e| {
-                                            log::error!("Failed to parse JSON state: {}", e);
-                                            rs9p::Error::No(EINVAL)
-                                        })?;
-                                    actor.state = new_state;
-                                    log::info!("Updated state for actor {}", actor_id);
-                                    return Ok(data.len() as u32);
-                                }
-                                "mailbox" => {
-                                    // Parse and add message to mailbox
-                                    if let Ok(messages) = serde_json::from_str(content) {
-                                        actor.mailbox = messages;
-                                        log::info!("Updated mailbox of actor {}", actor_id);
-                                    }
-                                    return Ok(data.len() as u32);
-                                }
-                                "script" => {
-                                    actor.script = content.to_owned();
-                                    log::info!("Updated script of actor {}", actor_id);
-                                    return Ok(data.len() as u32);
-                                }
-                                "paused" => {
-                                    // Parse and update paused state
-                                    let paused: bool = content.trim().parse().map_err(|e| {
-                                        log::error!("Failed to parse paused state: {}", e);
-                                        rs9p::Error::No(EINVAL)
-                                    })?;
-                                    actor.paused = paused;
-                                    log::info!(
-                                        "Updated paused state for actor {} to {}",
-                                        actor_id,
-                                        paused
-                                    );
-                                    return Ok(data.len() as u32);
-                                }
-                                _ => {}
+        if let Some(DbNode::Key(db, key)) = db_node(path) {
+            check_writable(&db)?;
+            let value: serde_json::Value = serde_json::from_str(content).map_err(|e| {
+                log::error!("Failed to parse JSON value: {}", e);
+                rs9p::Error::No(EINVAL)
+            })?;
+            db.store(&key, value).map_err(db_error)?;
+            log::info!("Updated {}", path);
+            return Ok(data.len() as u32);
+        }
+
+        if path.starts_with("/actors/") {
+            let parts: Vec<&str> = path.trim_start_matches("/actors/").split('/').collect();
+            if parts.len() >= 2 {
+                let actor_id = parts[0];
+                if parts[1] == "script" && self.new_actors.read().await.contains_key(actor_id) {
+                    self.spawn_new_actor(sys, actor_id, content).await?;
+                    return Ok(data.len() as u32);
+                }
+                if let Some(actor) = sys.actors.get_mut(actor_id) {
+                    match parts[1] {
+                        "state" => {
+                            // Parse and update actor state
+                            let new_state: serde_json::Value = serde_json::from_str(content)
+                                .map_err(|e| {
+                                    log::error!("Failed to parse JSON state: {}", e);
+                                    rs9p::Error::No(EINVAL)
+                                })?;
+                            actor.set_state(new_state.clone());
+                            log::info!("Updated state for actor {}", actor_id);
+                            sys.emit(|| EventKind::StateChanged {
+                                actor: actor_id.to_owned(),
+                                state: new_state.clone(),
+                            });
+                            sys.record(|| JournalEvent::StateWritten {
+                                actor: actor_id.to_owned(),
+                                state: new_state,
+                            });
+                            return Ok(data.len() as u32);
+                        }
+                        "mailbox" => {
+                            // Parse and replace the whole mailbox
+                            let messages: Vec<Message> =
+                                serde_json::from_str(content).map_err(|e| {
+                                    log::error!("Failed to parse JSON mailbox: {}", e);
+                                    rs9p::Error::No(EINVAL)
+                                })?;
+                            actor.mailbox = messages.clone().into();
+                            actor.changes.mailbox.touch();
+                            log::info!("Updated mailbox of actor {}", actor_id);
+                            sys.record(|| JournalEvent::MailboxWritten {
+                                actor: actor_id.to_owned(),
+                                mailbox: messages,
+                            });
+                            return Ok(data.len() as u32);
+                        }
+                        "inbox" => {
+                            // Parse every line before delivering any of them
+                            let messages = content
+                                .lines()
+                                .filter(|line| !line.trim().is_empty())
+                                .map(|line| parse_inbox_line(actor_id, line))
+                                .collect::<serde_json::Result<Vec<_>>>()
+                                .map_err(|e| {
+                                    log::error!("Failed to parse inbox line: {}", e);
+                                    rs9p::Error::No(EINVAL)
+                                })?;
+                            log::info!(
+                                "Delivering {} messages to actor {}",
+                                messages.len(),
+                                actor_id
+                            );
+                            for message in messages {
+                                sys.deliver(message);
                             }
-                        } else {
-                            return Err(rs9p::Error::No(ENOENT));
+                            return Ok(data.len() as u32);
+                        }
+                        "script" => {
+                            // Compile and migrate before the new script takes over
+                            sys.reload_actor(actor_id, content)
+                                .await
+                                .map_err(|_| rs9p::Error::No(EINVAL))?;
+                            log::info!("Updated script of actor {}", actor_id);
+                            return Ok(data.len() as u32);
                         }
+                        "paused" => {
+                            // Parse and update paused state
+                            let paused: bool = content.trim().parse().map_err(|e| {
+                                log::error!("Failed to parse paused state: {}", e);
+                                rs9p::Error::No(EINVAL)
+                            })?;
+                            actor.set_paused(paused);
+                            log::info!("Updated paused state for actor {} to {}", actor_id, paused);
+                            return Ok(data.len() as u32);
+                        }
+                        _ => {}
                     }
+                } else {
+                    return Err(rs9p::Error::No(ENOENT));
                 }
-                // File not writable or doesn't exist
-                Err(rs9p::Error::No(EROFS))
             }
         }
+        // File not writable or doesn't exist
+        Err(rs9p::Error::No(EROFS))
+    }
+
+    /// Start receiving the events of the system, or only those about `actor`
+    async fn subscribe(&self, actor: Option<String>) -> EventStream {
+        EventStream {
+            receiver: self.sys.read().await.events.subscribe(),
+            actor,
+            unread: Vec::new(),
+        }
+    }
+
+    /// Append data written to an actor's `inbox`
+    ///
+    /// Unlike other files, the inbox isn't written when the file is closed: every
+    /// complete line is delivered as soon as it is written, so a malformed line fails
+    /// the write that contains it. Only an unterminated last line waits in the write
+    /// buffer for the next write or the close. Offsets are ignored, the inbox only appends.
+    async fn write_inbox(&self, fid: &FId<MyFId>, path: &str, data: &Data) -> Result<FCall> {
+        let mut write_buffer = fid.aux.write_buffer.write().await;
+        let buffer = write_buffer.get_or_insert_with(Vec::new);
+        buffer.extend_from_slice(&data.0);
+
+        if let Some(end) = buffer.iter().rposition(|byte| *byte == b'\n') {
+            let lines: Vec<u8> = buffer.drain(..=end).collect();
+            let mut sys = self.sys.write().await;
+            self.write_file(&mut sys, path, &lines).await?;
+        }
+
+        Ok(FCall::RWrite {
+            count: data.0.len() as u32,
+        })
+    }
+
+    /// Create an empty file in the virtual filesystem
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - Mutable reference to the System
+    /// * `path` - Virtual filesystem path of the new file
+    async fn create_file(&self, sys: &mut System, path: &str) -> Result<()> {
+        if let Some(DbNode::Key(db, key)) = db_node(path) {
+            check_writable(&db)?;
+            if !db.exists(&key).map_err(db_error)? {
+                db.store(&key, serde_json::Value::Null).map_err(db_error)?;
+                log::info!("Created {}", path);
+            }
+            return Ok(());
+        }
+        if let Some(id) = path.strip_prefix("/actors/")
+            && let Some((id, file)) = id.split_once('/')
+        {
+            // the script of a new actor is written next, every other file already exists
+            let is_new = file == "script" && self.new_actors.read().await.contains_key(id);
+            if is_new || self.get_path_info(sys, path).await?.0 {
+                return Ok(());
+            }
+        }
+        Err(rs9p::Error::No(EACCES))
+    }
+
+    /// Spawn the actor of a directory made with `mkdir`, running `script`
+    ///
+    /// If the script fails to compile or `init` fails, the write fails with `EINVAL`
+    /// and the directory keeps why in its `errors` and `diagnostics` files, so the
+    /// script can be fixed and written again.
+    async fn spawn_new_actor(&self, sys: &mut System, id: &str, script: &str) -> Result<()> {
+        let props = Props {
+            script: script.to_owned(),
+            id: Some(id.to_owned()),
+            ..Default::default()
+        };
+        if let Err(e) = sys.spawn_actor(props).await {
+            log::error!("Failed to spawn actor {}: {}", id, e);
+            if let Some(new_actor) = self.new_actors.write().await.get_mut(id) {
+                new_actor.errors = e.to_string();
+                new_actor.diagnostics = match e {
+                    EosError::Compile(diagnostics) => diagnostics,
+                    _ => Vec::new(),
+                };
+            }
+            return Err(rs9p::Error::No(EINVAL));
+        }
+        self.new_actors.write().await.remove(id);
+        log::info!("Spawned actor {} from its script file", id);
+        Ok(())
+    }
+
+    /// Remove a file from the virtual filesystem
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - Mutable reference to the System
+    /// * `path` - Virtual filesystem path of the file
+    async fn remove_path(&self, sys: &mut System, path: &str) -> Result<()> {
+        if let Some(DbNode::Key(db, key)) = db_node(path) {
+            check_writable(&db)?;
+            if !db.exists(&key).map_err(db_error)? {
+                return Err(rs9p::Error::No(ENOENT));
+            }
+            db.delete(&key).map_err(db_error)?;
+            log::info!("Removed {}", path);
+            return Ok(());
+        }
+        if let Some(id) = path.strip_prefix("/actors/")
+            && !id.is_empty()
+            && !id.contains('/')
+        {
+            if self.new_actors.write().await.remove(id).is_some() {
+                return Ok(());
+            }
+            if !sys.actors.contains_key(id) {
+                return Err(rs9p::Error::No(ENOENT));
+            }
+            sys.kill_actor(id).await.map_err(|e| {
+                log::error!("Failed to kill actor {}: {}", id, e);
+                rs9p::Error::No(EIO)
+            })?;
+            return Ok(());
+        }
+        Err(rs9p::Error::No(EPERM))
+    }
+
+    /// Format the value stored under `key` as pretty-printed JSON
+    ///
+    /// Returns `None` if the key doesn't exist.
+    fn format_db_value(&self, db: &Db, key: &str) -> Result<Option<String>> {
+        let value = db.load::<serde_json::Value>(key).map_err(db_error)?;
+        Ok(value.map(|value| serde_json::to_string_pretty(&value).unwrap_or_default()))
+    }
+
+    /// Format the cache stats of a db as human-readable text
+    fn format_db_stats(&self, db: &Db) -> String {
+        db.stats()
+            .map(|stats| format!("{:#?}", stats))
+            .unwrap_or_default()
+    }
+
+    /// Format the files a library script was compiled from, one path per line
+    fn format_script_files(&self, sys: &System, script: ScriptRef) -> String {
+        format_lines(&sys.scripts.files(script))
     }
 
     /// Format the spawn queue as human-readable text
@@ -1023,6 +2182,14 @@ impl FsOverlay {
         serde_json::to_string_pretty(&sys.spawn_queue).unwrap_or_else(|_| s!("[]"))
     }
 
+    /// Format the dead letters as human-readable text
+    ///
+    /// Returns a formatted string showing every undeliverable message together
+    /// with the reason it was dropped and the tick it happened in.
+    fn format_dead_letters(&self, sys: &System) -> String {
+        serde_json::to_string_pretty(&sys.dead_letters).unwrap_or_else(|_| s!("[]"))
+    }
+
     /// Format an actor's mailbox as human-readable text
     ///
     /// Returns a formatted string showing all messages in the actor's
@@ -1030,4 +2197,40 @@ impl FsOverlay {
     fn format_mailbox(&self, actor: &crate::system::Actor) -> String {
         serde_json::to_string_pretty(&actor.mailbox).unwrap_or_else(|_| s!("[]"))
     }
+
+    /// Format what the compiler found in an actor's script as a JSON array
+    ///
+    /// These are the errors of the last script written to the actor if it was
+    /// rejected, and the warnings of the script it runs otherwise.
+    fn format_actor_diagnostics(&self, sys: &System, actor: &crate::system::Actor) -> String {
+        let diagnostics = if actor.script_diagnostics.is_empty() {
+            sys.scripts.diagnostics(actor.script_ref())
+        } else {
+            &actor.script_diagnostics
+        };
+        format_diagnostics(diagnostics)
+    }
+
+    /// Format the timers an actor scheduled as a JSON array, with when each is due
+    fn format_timers(&self, sys: &System, actor: &crate::system::Actor) -> String {
+        let timers: Vec<_> = sys.timers.of(&actor.id).collect();
+        serde_json::to_string_pretty(&timers).unwrap_or_else(|_| s!("[]"))
+    }
+
+    /// Format the last error of an actor's handler as human-readable text
+    ///
+    /// Returns the tick, error and failing message followed by the script's
+    /// backtrace, or an empty string if the handler never failed.
+    fn format_error(&self, actor: &crate::system::Actor) -> String {
+        let Some(error) = &actor.last_error else {
+            return String::new();
+        };
+        format!(
+            "tick: {}\nerror: {}\nmessage: {}\n\n{}",
+            error.tick,
+            error.error,
+            serde_json::to_string(&error.message).unwrap_or_default(),
+            error.backtrace
+        )
+    }
 }
@@ -0,0 +1,195 @@
+//! Groups of identical actors that are addressed by a single id
+//!
+//! `eos group create` spawns the members of a group from one script. Messages sent to
+//! the id of the group are routed to its members by the group's [`Routing`], so a
+//! group can be used like a single actor that does its work in parallel. Members are
+//! regular actors with the ids `{group}-{n}`, which leave the group when they are
+//! killed. The group is gone once its last member is.
+//!
+//! Routing only depends on the group and the message, so a replayed group, which
+//! routes the journaled messages again, picks the same members as the live one.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::common::fnv1a;
+
+/// Which members of a group a message sent to the group goes to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum Routing {
+    /// one member after the other
+    #[default]
+    RoundRobin,
+    /// a member picked at random
+    Random,
+    /// the same member for messages with the same key, see [`Group::hash_key`]
+    ConsistentHash,
+    /// every member
+    Broadcast,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Group {
+    pub id: String,
+    pub routing: Routing,
+    /// the field of the payload that is hashed with [`Routing::ConsistentHash`], the
+    /// whole payload is hashed without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash_key: Option<String>,
+    pub members: Vec<String>,
+    /// the member the next message goes to with [`Routing::RoundRobin`]
+    #[serde(default)]
+    next: usize,
+    /// the state of the random numbers of [`Routing::Random`], seeded from the id
+    #[serde(default)]
+    random: u64,
+}
+
+impl Group {
+    pub fn new(id: &str, routing: Routing, hash_key: Option<String>) -> Self {
+        Group {
+            id: id.to_owned(),
+            routing,
+            hash_key,
+            members: Vec::new(),
+            next: 0,
+            random: fnv1a(id.bytes()),
+        }
+    }
+
+    /// The id of the `n`th member
+    pub fn member_id(&self, n: usize) -> String {
+        format!("{}-{n}", self.id)
+    }
+
+    /// The members a message with `payload` goes to, none if the group is empty
+    pub fn route(&mut self, payload: &JsonValue) -> Vec<String> {
+        if self.members.is_empty() {
+            return Vec::new();
+        }
+        let member = match self.routing {
+            Routing::Broadcast => return self.members.clone(),
+            Routing::RoundRobin => {
+                let member = self.next % self.members.len();
+                self.next = member + 1;
+                member
+            }
+            Routing::Random => (self.next_random() % self.members.len() as u64) as usize,
+            Routing::ConsistentHash => {
+                let key = match &self.hash_key {
+                    Some(field) => payload.get(field).unwrap_or(&JsonValue::Null),
+                    None => payload,
+                };
+                self.rendezvous(&key.to_string())
+            }
+        };
+        vec![self.members[member].clone()]
+    }
+
+    /// Picks the member with the highest hash of itself and `key`
+    ///
+    /// Unlike taking the hash modulo the number of members, this only moves the keys
+    /// of a member that leaves the group.
+    fn rendezvous(&self, key: &str) -> usize {
+        (0..self.members.len())
+            .max_by_key(|&n| {
+                // ids can't contain NUL, so it separates the key from the member
+                fnv1a(key.bytes().chain([0]).chain(self.members[n].bytes()))
+            })
+            .unwrap_or_default()
+    }
+
+    /// The next number of a SplitMix64 generator
+    fn next_random(&mut self) -> u64 {
+        self.random = self.random.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.random;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Removes the member `id`, returns false if it wasn't one
+    pub fn remove_member(&mut self, id: &str) -> bool {
+        let len = self.members.len();
+        self.members.retain(|member| member != id);
+        self.members.len() != len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn group(routing: Routing, hash_key: Option<&str>, size: usize) -> Group {
+        let mut group = Group::new("g", routing, hash_key.map(str::to_owned));
+        group.members = (0..size).map(|n| group.member_id(n)).collect();
+        group
+    }
+
+    #[test]
+    fn round_robin_takes_turns() {
+        let mut group = group(Routing::RoundRobin, None, 3);
+        let picked: Vec<_> = (0..4).flat_map(|_| group.route(&json!({}))).collect();
+        assert_eq!(picked, ["g-0", "g-1", "g-2", "g-0"]);
+    }
+
+    #[test]
+    fn broadcast_goes_to_every_member() {
+        let mut group = group(Routing::Broadcast, None, 3);
+        assert_eq!(group.route(&json!(1)), ["g-0", "g-1", "g-2"]);
+    }
+
+    #[test]
+    fn empty_groups_route_nowhere() {
+        for routing in [
+            Routing::RoundRobin,
+            Routing::Random,
+            Routing::ConsistentHash,
+            Routing::Broadcast,
+        ] {
+            assert!(group(routing, None, 0).route(&json!(1)).is_empty());
+        }
+    }
+
+    #[test]
+    fn random_routing_is_reproducible() {
+        let mut a = group(Routing::Random, None, 5);
+        let mut b = a.clone();
+        let picked: Vec<_> = (0..50).flat_map(|_| a.route(&json!(1))).collect();
+        assert_eq!(
+            picked,
+            (0..50).flat_map(|_| b.route(&json!(1))).collect::<Vec<_>>()
+        );
+        // every member gets some of the messages
+        for member in &a.members {
+            assert!(picked.contains(member));
+        }
+    }
+
+    #[test]
+    fn consistent_hash_keeps_keys_on_their_member() {
+        let mut group = group(Routing::ConsistentHash, Some("user"), 4);
+        let users: Vec<_> = (0..20).map(|n| json!({ "user": n, "n": 1 })).collect();
+        let before: Vec<_> = users.iter().map(|user| group.route(user)).collect();
+        // the other fields of the payload don't matter
+        assert_eq!(group.route(&json!({ "user": 0, "n": 2 })), before[0]);
+
+        assert!(group.remove_member("g-1"));
+        for (user, before) in users.iter().zip(before) {
+            if before != ["g-1"] {
+                assert_eq!(group.route(user), before);
+            }
+        }
+    }
+
+    #[test]
+    fn removes_members() {
+        let mut group = group(Routing::RoundRobin, None, 2);
+        assert!(group.remove_member("g-0"));
+        assert!(!group.remove_member("g-0"));
+        assert_eq!(group.members, ["g-1"]);
+    }
+}
@@ -0,0 +1,573 @@
+//! Write-ahead journal of everything that changes the actor system, and its replay
+//!
+//! While `eos serve --journal <name>` runs, every delivered and handled message,
+//! spawn, restart, kill, timer change, subscription, group, watch, link and external
+//! write is recorded in order. Once per tick the
+//! events are committed to the db `<name>` as a [`TickRecord`], together with the
+//! states the touched actors ended up with. The system the journal started from is
+//! stored under [`BASE`], so [`replay`] can rebuild the system at any recorded tick
+//! by re-running each actor's `handle` on exactly the messages it handled back then.
+//!
+//! Replay never decides anything on its own: delivery, scheduling and failure
+//! handling are taken from the journal, only the scripts run again. A replayed state
+//! that differs from the recorded one, or a script that generates other ids than back
+//! then, is reported as a [`Divergence`], which points at the first message that made
+//! a script behave differently.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
+
+use nanoid::nanoid;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::{
+    common::{Db, DeadLetterReason, Message, Props, dirs::STORAGE},
+    groups::Group,
+    scripts,
+    system::{Actor, System},
+    timers::{Due, Scheduled},
+};
+
+/// key of the system the journal started from
+pub const BASE: &str = "base";
+/// key of the last committed tick
+pub const HEAD: &str = "head";
+
+fn tick_key(tick: u64) -> String {
+    format!("tick/{tick:020}")
+}
+
+/// A single change to the system, in the order it happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JournalEvent {
+    /// a message was put into the mailbox of an existing actor
+    Delivered {
+        message: Message,
+    },
+    /// an actor took `message` from the front of its mailbox and ran `handle`
+    Handled {
+        actor: String,
+        message: Message,
+        /// ids generated by `spawn`, `ask` and the timers while handling the message
+        ids: Vec<String>,
+    },
+    /// a failed message was put back at the front of the mailbox
+    Requeued {
+        actor: String,
+        message: Message,
+    },
+    /// an actor was spawned, `props.id` is always set
+    Spawned {
+        props: Props,
+        /// ids generated by `spawn` in `init`
+        ids: Vec<String>,
+    },
+    /// an actor's `init` ran again
+    Restarted {
+        actor: String,
+        /// ids generated by `spawn` in `init`
+        ids: Vec<String>,
+    },
+    Killed {
+        actor: String,
+    },
+    DeadLettered {
+        message: Message,
+        reason: DeadLetterReason,
+    },
+    StateWritten {
+        actor: String,
+        state: JsonValue,
+    },
+    MailboxWritten {
+        actor: String,
+        mailbox: Vec<Message>,
+    },
+    ScriptWritten {
+        actor: String,
+        script: String,
+    },
+    /// a timer was started, replacing any timer with its id
+    TimerScheduled {
+        scheduled: Scheduled,
+    },
+    TimerCancelled {
+        id: String,
+    },
+    /// a timer sent its message, `next` is when a repeating timer is due again
+    TimerFired {
+        id: String,
+        next: Option<Due>,
+    },
+    Subscribed {
+        actor: String,
+        pattern: String,
+    },
+    Unsubscribed {
+        actor: String,
+        pattern: String,
+    },
+    /// a group was created after its members were spawned, members leave it when
+    /// they are killed
+    GroupCreated {
+        group: Group,
+    },
+    /// a message with `payload` sent to `group` was routed to the members it picked,
+    /// which are recorded as deliveries
+    Routed {
+        group: String,
+        payload: JsonValue,
+    },
+    /// `watcher` started (`watch`) or stopped watching `actor`
+    Watched {
+        actor: String,
+        watcher: String,
+        watch: bool,
+    },
+    /// `actor` and `other` were linked (`link`) or unlinked
+    Linked {
+        actor: String,
+        other: String,
+        link: bool,
+    },
+    TrapExit {
+        actor: String,
+        trap: bool,
+    },
+    /// the system was rewound to an earlier tick
+    Restored {
+        system: JsonValue,
+    },
+}
+
+impl JournalEvent {
+    /// The actor whose state may have changed through this event
+    fn touches(&self) -> Option<&str> {
+        match self {
+            JournalEvent::Handled { actor, .. }
+            | JournalEvent::Restarted { actor, .. }
+            | JournalEvent::StateWritten { actor, .. } => Some(actor),
+            JournalEvent::Spawned { props, .. } => props.id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The actor whose script ran for this event, with the ids it generated
+    fn ids(&self) -> Option<(&str, &[String])> {
+        match self {
+            JournalEvent::Handled { actor, ids, .. } | JournalEvent::Restarted { actor, ids } => {
+                Some((actor, ids))
+            }
+            JournalEvent::Spawned { props, ids } => Some((props.id.as_deref()?, ids)),
+            _ => None,
+        }
+    }
+}
+
+/// Everything that happened since the previous tick, up to the end of `tick`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TickRecord {
+    pub tick: u64,
+    pub events: Vec<JournalEvent>,
+    /// states of the actors touched by `events` at the end of the tick
+    pub states: BTreeMap<String, JsonValue>,
+    /// spawn requests left for the next tick
+    pub spawn_queue: Vec<Props>,
+}
+
+/// The journal a running system records into
+#[derive(Debug)]
+pub struct Journal {
+    db: Db,
+    events: Vec<JournalEvent>,
+}
+
+impl Journal {
+    /// Starts the journal `name` from `sys`, replacing any previous journal of that name
+    pub fn start(name: &str, sys: &System) -> anyhow::Result<Self> {
+        let db = Db::new(STORAGE, name);
+        if std::fs::exists(Path::new(STORAGE).join(name))? {
+            log::warn!("replacing journal {name:?}");
+            db.remove()?;
+        }
+        db.store(BASE, serde_json::to_value(sys)?)?;
+        db.store(HEAD, sys.current_tick)?;
+        log::info!("journaling into {name:?} from tick {}", sys.current_tick);
+        Ok(Journal {
+            db,
+            events: Vec::new(),
+        })
+    }
+
+    pub fn record(&mut self, event: JournalEvent) {
+        self.events.push(event);
+    }
+
+    /// Writes the events recorded since the last commit as the record of `tick`
+    pub fn commit(
+        &mut self,
+        tick: u64,
+        actors: &HashMap<String, Actor>,
+        spawn_queue: &[Props],
+    ) -> anyhow::Result<()> {
+        let events = std::mem::take(&mut self.events);
+        let touched: BTreeSet<&str> = events.iter().filter_map(JournalEvent::touches).collect();
+        let states = touched
+            .into_iter()
+            .filter_map(|id| Some((id.to_owned(), actors.get(id)?.state.clone())))
+            .collect();
+        let record = TickRecord {
+            tick,
+            events,
+            states,
+            spawn_queue: spawn_queue.to_vec(),
+        };
+        self.db.store(&tick_key(tick), record)?;
+        self.db.store(HEAD, tick)?;
+        Ok(())
+    }
+}
+
+/// Something a replayed script did differently than recorded in the journal
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Divergence {
+    /// the state of `actor` at the end of `tick` differs from the recorded one
+    State {
+        tick: u64,
+        actor: String,
+        recorded: JsonValue,
+        /// `None` if the actor doesn't exist in the replayed system
+        replayed: Option<JsonValue>,
+    },
+    /// the script of `actor` generated other ids than the recorded ones, e.g. because
+    /// it spawned fewer children
+    Ids {
+        tick: u64,
+        actor: String,
+        recorded: Vec<String>,
+        replayed: Vec<String>,
+    },
+}
+
+/// The result of a [`replay`]
+#[derive(Debug)]
+pub struct Replay {
+    pub system: System,
+    pub divergences: Vec<Divergence>,
+}
+
+/// Rebuilds the system recorded in the journal `name` as it was at the end of `until_tick`
+///
+/// Replays up to the last recorded tick if `until_tick` is `None`. The replayed scripts
+/// work on scratch copies of the actor dbs, which are deleted afterwards, so the live
+/// dbs stay as they are. The ids and dbs of the replayed scripts are set up for the
+/// current thread, so the replay must not move to another one while it runs.
+pub async fn replay(name: &str, until_tick: Option<u64>) -> anyhow::Result<Replay> {
+    if !std::fs::exists(Path::new(STORAGE).join(name))? {
+        anyhow::bail!("Journal {name:?} not found");
+    }
+    let scratch =
+        std::env::temp_dir().join(format!("eos-replay-{}-{}", std::process::id(), nanoid!()));
+    scripts::set_scratch_dbs(Some(scratch.clone()));
+    let replay = replay_journal(name, until_tick).await;
+    scripts::set_scratch_dbs(None);
+    Db::close_all(&scratch);
+    if std::fs::exists(&scratch)? {
+        std::fs::remove_dir_all(&scratch)?;
+    }
+    replay
+}
+
+async fn replay_journal(name: &str, until_tick: Option<u64>) -> anyhow::Result<Replay> {
+    let db = Db::new(STORAGE, name);
+    let base = db
+        .load::<JsonValue>(BASE)?
+        .ok_or_else(|| anyhow::anyhow!("Journal {name:?} has no base snapshot"))?;
+    let mut sys: System = serde_json::from_value(base)?;
+    let head = db.load::<u64>(HEAD)?.unwrap_or(sys.current_tick);
+    let until = until_tick.unwrap_or(head);
+    if until < sys.current_tick {
+        anyhow::bail!(
+            "Journal {name:?} starts at tick {}, can't replay until tick {until}",
+            sys.current_tick
+        );
+    }
+    if until > head {
+        anyhow::bail!("Journal {name:?} ends at tick {head}, can't replay until tick {until}");
+    }
+
+    let mut divergences = Vec::new();
+    for tick in sys.current_tick + 1..=until {
+        let Some(record) = db.load::<TickRecord>(&tick_key(tick))? else {
+            continue;
+        };
+        for event in record.events {
+            let expected = event
+                .ids()
+                .map(|(actor, ids)| (actor.to_owned(), ids.to_vec()));
+            apply(&mut sys, event).await;
+            // ids one event didn't use must not end up in the next one
+            scripts::replay_ids(Vec::new());
+            let replayed = scripts::take_generated_ids();
+            if let Some((actor, recorded)) = expected
+                && replayed != recorded
+            {
+                log::warn!("replay generated other ids at tick {tick} for actor {actor}");
+                divergences.push(Divergence::Ids {
+                    tick,
+                    actor,
+                    recorded,
+                    replayed,
+                });
+            }
+        }
+        for (actor, recorded) in record.states {
+            let replayed = sys.actors.get(&actor).map(|actor| actor.state.clone());
+            if replayed.as_ref() != Some(&recorded) {
+                log::warn!("replay diverged at tick {tick} for actor {actor}");
+                divergences.push(Divergence::State {
+                    tick,
+                    actor,
+                    recorded,
+                    replayed,
+                });
+            }
+        }
+        sys.spawn_queue = record.spawn_queue;
+        sys.current_tick = record.tick;
+    }
+    Ok(Replay {
+        system: sys,
+        divergences,
+    })
+}
+
+/// Applies a recorded event, running scripts but ignoring the effects they request
+///
+/// This holds for `init` as much as for `handle`: whatever the effects caused back
+/// then is recorded as events of its own.
+async fn apply(sys: &mut System, event: JournalEvent) {
+    match event {
+        JournalEvent::Delivered { message } => {
+            if let Some(actor) = sys.actors.get_mut(&message.to) {
+                actor.mailbox.push_back(message);
+            }
+        }
+        JournalEvent::Handled {
+            actor: id,
+            message,
+            ids,
+        } => {
+            let Some(actor) = sys.actors.get_mut(&id) else {
+                log::error!("replay: actor {id} handled a message, but doesn't exist");
+                return;
+            };
+            if let Some(position) = actor.mailbox.iter().position(|m| *m == message) {
+                actor.mailbox.remove(position);
+            }
+            scripts::replay_ids(ids);
+            if let Err(e) = actor.run(&mut sys.scripts, message).await {
+                log::warn!("replay: actor {id} failed: {e}");
+            }
+        }
+        JournalEvent::Requeued { actor, message } => {
+            if let Some(actor) = sys.actors.get_mut(&actor) {
+                actor.mailbox.push_front(message);
+            }
+        }
+        JournalEvent::Spawned { props, ids } => {
+            scripts::replay_ids(ids);
+            if let Err(e) = sys.create_actor(props).await {
+                log::error!("replay: failed to spawn actor: {e}");
+            }
+        }
+        JournalEvent::Restarted { actor: id, ids } => {
+            scripts::replay_ids(ids);
+            if let Some(actor) = sys.actors.get_mut(&id)
+                && let Err(e) = actor.restart(&mut sys.scripts).await
+            {
+                log::error!("replay: failed to restart actor {id}: {e}");
+            }
+        }
+        JournalEvent::Killed { actor } => {
+            sys.remove_actor(&actor);
+        }
+        JournalEvent::DeadLettered { message, reason } => {
+            sys.dead_letter(message, reason);
+        }
+        JournalEvent::StateWritten { actor, state } => {
+            if let Some(actor) = sys.actors.get_mut(&actor) {
+                actor.state = state;
+            }
+        }
+        JournalEvent::MailboxWritten { actor, mailbox } => {
+            if let Some(actor) = sys.actors.get_mut(&actor) {
+                actor.mailbox = mailbox.into();
+            }
+        }
+        JournalEvent::ScriptWritten { actor, script } => {
+            if let Some(actor) = sys.actors.get_mut(&actor) {
+                actor.script = script;
+                actor.file = None;
+            }
+        }
+        JournalEvent::TimerScheduled { scheduled } => {
+            sys.timers.insert(scheduled);
+        }
+        JournalEvent::TimerCancelled { id } => {
+            sys.timers.cancel(&id);
+        }
+        JournalEvent::TimerFired { id, next } => {
+            if let Some(timer) = sys.timers.cancel(&id)
+                && let Some(due) = next
+            {
+                sys.timers.insert(Scheduled { timer, due });
+            }
+        }
+        JournalEvent::Subscribed { actor, pattern } => {
+            sys.topics.subscribe(&pattern, &actor);
+        }
+        JournalEvent::Unsubscribed { actor, pattern } => {
+            sys.topics.unsubscribe(&pattern, &actor);
+        }
+        JournalEvent::GroupCreated { group } => {
+            sys.groups.insert(group.id.clone(), group);
+        }
+        JournalEvent::Routed { group, payload } => {
+            // routes again to move round-robin and random routing on like back then
+            if let Some(group) = sys.groups.get_mut(&group) {
+                group.route(&payload);
+            }
+        }
+        JournalEvent::Watched {
+            actor,
+            watcher,
+            watch,
+        } => {
+            if let Some(actor) = sys.actors.get_mut(&actor) {
+                if watch {
+                    actor.watchers.insert(watcher);
+                } else {
+                    actor.watchers.remove(&watcher);
+                }
+            }
+        }
+        JournalEvent::Linked { actor, other, link } => {
+            sys.set_link(&actor, &other, link);
+        }
+        JournalEvent::TrapExit { actor, trap } => {
+            if let Some(actor) = sys.actors.get_mut(&actor) {
+                actor.trap_exit = trap;
+            }
+        }
+        JournalEvent::Restored { system } => match serde_json::from_value::<System>(system) {
+            Ok(mut restored) => {
+                restored.scripts = std::mem::take(&mut sys.scripts);
+                *sys = restored;
+            }
+            Err(e) => log::error!("replay: failed to restore system: {e}"),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::common::dirs::ACTOR_DBS;
+
+    /// Runs `ticks` ticks of a system with the actor `id` running `script`, journaled
+    /// into a fresh journal, whose name is returned
+    async fn record(id: &str, script: &str, messages: &[JsonValue], ticks: u64) -> String {
+        let name = format!("test-journal-{}", nanoid!());
+        let mut sys = System::new();
+        sys.journal = Some(Journal::start(&name, &sys).unwrap());
+        sys.spawn_actor(Props {
+            script: script.to_owned(),
+            id: Some(id.to_owned()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        for payload in messages {
+            sys.deliver(Message {
+                to: id.to_owned(),
+                payload: payload.clone(),
+                ..Default::default()
+            });
+        }
+        for _ in 0..ticks {
+            sys.tick().await.unwrap();
+        }
+        name
+    }
+
+    fn remove(name: &str, id: &str) {
+        Db::new(STORAGE, name).remove().unwrap();
+        Db::new(Path::new(STORAGE).join(ACTOR_DBS), id)
+            .remove()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn replays_the_ticks_of_a_journal() {
+        let id = format!("counter-{}", nanoid!());
+        let script = "
+            pub fn init() { #{ n: 0 } }
+            pub fn handle(state, msg) { state.n += msg; state }
+        ";
+        let name = record(&id, script, &[json!(1), json!(2), json!(3)], 3).await;
+
+        let to_end = replay(&name, None).await.unwrap();
+        assert_eq!(to_end.system.current_tick, 3);
+        assert_eq!(to_end.system.actors[&id].state["n"], 6);
+        assert!(to_end.divergences.is_empty(), "{:?}", to_end.divergences);
+
+        let to_first = replay(&name, Some(1)).await.unwrap();
+        assert_eq!(to_first.system.current_tick, 1);
+        assert_eq!(to_first.system.actors[&id].state["n"], 1);
+        remove(&name, &id);
+    }
+
+    #[tokio::test]
+    async fn reports_ids_a_replayed_script_did_not_generate() {
+        // the live db already has the flag when the replay copies it
+        let id = format!("parent-{}", nanoid!());
+        let script = r#"
+            pub fn init() { #{} }
+            pub fn handle(state, msg) {
+                if !db_exists("spawned") {
+                    db_store("spawned", true);
+                    spawn("pub fn handle(state, msg) { state }");
+                }
+                state
+            }
+        "#;
+        let name = record(&id, script, &[json!("go")], 2).await;
+
+        let replay = replay(&name, None).await.unwrap();
+        let [
+            Divergence::Ids {
+                tick,
+                actor,
+                recorded,
+                replayed,
+            },
+        ] = replay.divergences.as_slice()
+        else {
+            panic!("{:?}", replay.divergences);
+        };
+        assert_eq!((*tick, actor), (1, &id));
+        assert_eq!(recorded.len(), 1);
+        assert!(replayed.is_empty());
+        // the child is spawned from the journal all the same
+        assert!(replay.system.actors.contains_key(&recorded[0]));
+        remove(&name, &id);
+    }
+}
@@ -1,4 +1,7 @@
 use std::{
+    collections::BTreeMap,
+    io::IsTerminal,
+    ops::Bound,
     path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
@@ -10,9 +13,14 @@ use axum::{Json, Router, extract::State, routing::post};
 #[cfg(feature = "_setup")]
 use clap::Command;
 use clap::{Parser, Subcommand};
-use common::{Message, Props, Response};
+use common::{
+    AskRequest, DbCall, DbRequest, ErrorPolicy, ForkRequest, GroupRequest, Message, Props,
+    PublishRequest, ReloadRequest, ReplayRequest, Response, RestartStrategy, SupervisorSpec,
+    TickPolicy,
+};
 
 use rs9p::srv::srv_async;
+use rune::termcolor::{ColorChoice, StandardStream};
 use serde::Serialize;
 use stringlit::s;
 
@@ -22,17 +30,25 @@ use tokio::sync::RwLock;
 
 use crate::{
     common::{
-        DEFAULT_TICK, EOS_RPC_PORT, KILL_FILE,
+        DEFAULT_ASK_TIMEOUT, DEFAULT_MAX_RESTARTS, DEFAULT_RESTART_WINDOW,
+        DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_TICK, EOS_RPC_PORT, KILL_FILE, LATEST_SNAPSHOT,
         dirs::{LOGS, STORAGE},
         teleplot,
     },
     file_overlay::FsOverlay,
-    system::System,
+    groups::Routing,
+    journal::{Journal, Replay},
+    system::{EosError, System},
 };
 
 mod common;
 mod file_overlay;
+mod groups;
+mod journal;
+mod scripts;
 mod system;
+mod timers;
+mod topics;
 
 #[cfg(feature = "_setup")]
 #[derive(Parser)]
@@ -68,14 +84,72 @@ enum Action {
         /// if and where to mount its internal state
         #[arg(default_value = "tcp!127.0.0.1!7797")]
         endpoint: String,
+        /// the name of a snapshot to continue from, e.g. "latest"
+        #[arg(long)]
+        restore: Option<String>,
+        /// seconds between two snapshots of the system, 0 disables periodic snapshots
+        #[arg(long, default_value_t = DEFAULT_SNAPSHOT_INTERVAL)]
+        snapshot_interval: u64,
+        /// records every change into the journal with this name, replacing an existing one
+        #[arg(long)]
+        journal: Option<String>,
+        /// directories library scripts are looked up in, separated by ':'
+        #[arg(long, value_delimiter = ':', default_value = ".")]
+        script_path: Vec<PathBuf>,
+    },
+    /// rebuilds the system from a journal by running the recorded messages again
+    Replay {
+        /// the name of the journal
+        journal: String,
+        /// the last tick to replay, the end of the journal if omitted
+        #[arg(long)]
+        until_tick: Option<u64>,
+        /// stores the rebuilt system as a snapshot with this name
+        #[arg(long)]
+        save: Option<String>,
+    },
+    /// stores a snapshot of the system
+    Snapshot {
+        /// the name of the snapshot
+        #[arg(default_value = LATEST_SNAPSHOT)]
+        name: String,
+    },
+    /// continues the system from the end of an earlier tick
+    Fork {
+        /// the tick to continue from
+        tick: u64,
+        /// stores the abandoned timeline as a snapshot with this name
+        #[arg(long)]
+        keep: Option<String>,
     },
     /// spawn an actor
     Spawn {
         /// the requested id for the actor
         #[arg(short, long)]
         id: Option<String>,
+        /// the directory of the actor that restarts this one when it fails
+        #[arg(long)]
+        supervisor: Option<PathBuf>,
+        /// which children this actor restarts when one of them fails
+        #[arg(long, value_enum, default_value_t)]
+        strategy: RestartStrategy,
+        /// how often this actor restarts children within the restart window before giving up
+        #[arg(long, default_value_t = DEFAULT_MAX_RESTARTS)]
+        max_restarts: u32,
+        /// the restart window in ticks
+        #[arg(long, default_value_t = DEFAULT_RESTART_WINDOW)]
+        within: u64,
+        /// runs the actor from the script file instead of sending the script's source
+        #[arg(long)]
+        file: bool,
+        /// a script file, with `--file` also the name of a script in the server's script path
         script: PathBuf,
     },
+    /// manages groups of actors addressed by a single id
+    Group {
+        #[command(subcommand)]
+        command: GroupCommand,
+    },
     /// Kill an actor
     Kill {
         /// the directories for the actors to kill
@@ -93,6 +167,16 @@ enum Action {
         /// the directory for the actor to unpause
         path: Option<PathBuf>,
     },
+    /// replaces the script of an actor, migrating its state with the script's `migrate`
+    Reload {
+        /// also reloads every other actor running the same script
+        #[arg(long)]
+        shared: bool,
+        /// the directory for the actor to reload
+        path: PathBuf,
+        /// the new script
+        script: PathBuf,
+    },
     /// puts message in the send queue and notifies the supervisor that a message is available
     Send {
         /// the id of the sender
@@ -103,11 +187,38 @@ enum Action {
         /// a string containing the json representation of a message
         msg: String,
     },
+    /// sends a message and prints the reply
+    Ask {
+        /// how many ticks to wait for the reply
+        #[arg(long, default_value_t = DEFAULT_ASK_TIMEOUT)]
+        timeout: u64,
+        /// the path to the actor the message should be sent
+        path: PathBuf,
+        /// a string containing the json representation of a message
+        msg: String,
+    },
+    /// sends a message to every actor subscribed to a topic
+    Publish {
+        /// the topic, like `sensors.kitchen.temp`
+        topic: String,
+        /// a string containing the json representation of a message
+        msg: String,
+    },
     /// changes the tick rate of the system
     Tick {
         #[command(subcommand)]
         command: TickCommand,
     },
+    /// inspects messages that could not be delivered
+    DeadLetters {
+        #[command(subcommand)]
+        command: DeadLetterCommand,
+    },
+    /// sets what happens to a message when an actor fails to handle it
+    OnError {
+        #[command(subcommand)]
+        policy: ErrorPolicyCommand,
+    },
     /// handles "db" access
     Db {
         /// the db name
@@ -150,12 +261,90 @@ enum DbCommand {
         /// the key to check
         key: String,
     },
+    /// list the keys in an actors kv-store
+    Keys {
+        /// only list keys starting with this prefix
+        #[arg(long, default_value = "")]
+        prefix: String,
+    },
+    /// count the values in an actors kv-store
+    Count,
+    /// print all values in an actors kv-store as a JSON object
+    Dump {
+        /// the first key to include
+        #[arg(long)]
+        from: Option<String>,
+        /// the first key to leave out
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// store every entry of a JSON object in an actors kv-store, in a single transaction
+    Import {
+        /// the file holding the JSON object, "-" for stdin
+        file: PathBuf,
+        /// skip keys that already exist instead of overwriting them
+        #[arg(long)]
+        keep_existing: bool,
+    },
     /// compact the db for an actor
     Compact,
     /// print cache stats for the db of an actor
     Stats,
 }
 
+#[derive(Subcommand)]
+enum GroupCommand {
+    /// spawns a group of actors running the same script
+    Create {
+        /// the id of the group, its members get the ids `{id}-{n}`
+        #[arg(short, long)]
+        id: Option<String>,
+        /// a script file, with `--file` also the name of a script in the server's script path
+        #[arg(long)]
+        script: PathBuf,
+        /// runs the actor from the script file instead of sending the script's source
+        #[arg(long)]
+        file: bool,
+        /// how many actors to spawn
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        size: u32,
+        /// which members a message sent to the group goes to
+        #[arg(long, value_enum, default_value_t)]
+        routing: Routing,
+        /// the field of the payload that picks the member with consistent-hash routing
+        #[arg(long)]
+        hash_key: Option<String>,
+    },
+    /// kills all members of a group
+    Delete {
+        /// the directory of the group
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum DeadLetterCommand {
+    /// lists all dead letters
+    List,
+    /// tries to deliver all dead letters again
+    Replay,
+    /// removes all dead letters
+    Clear,
+}
+
+#[derive(Subcommand)]
+enum ErrorPolicyCommand {
+    /// pauses the actor and keeps the message in its mailbox
+    Pause,
+    /// moves the message to the dead letters
+    Drop,
+    /// retries the message before moving it to the dead letters
+    Retry {
+        /// how often the message is retried
+        times: u32,
+    },
+}
+
 #[derive(Subcommand)]
 enum TickCommand {
     /// sets the tick rate of the system
@@ -168,6 +357,31 @@ enum TickCommand {
     Reset,
     /// Ticks once
     Now,
+    /// steps the system back to the end of an earlier tick
+    Back {
+        /// how many ticks to step back
+        #[arg(default_value_t = 1)]
+        ticks: u64,
+    },
+    /// prints the current tick policy
+    Policy,
+    /// sets how many messages each actor handles per tick
+    Budget {
+        /// the number of messages per actor and tick (must be 1 or higher)
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        messages: u64,
+    },
+    /// enables or disables handling every queued message of an actor each tick
+    Drain {
+        #[arg(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// sets how many messages are handled per tick across all actors
+    GlobalBudget {
+        /// the number of messages per tick, unlimited if omitted
+        #[arg(value_parser = clap::value_parser!(u64).range(1..))]
+        messages: Option<u64>,
+    },
 }
 
 #[derive(Clone)]
@@ -176,15 +390,24 @@ struct AppState {
     sys: Arc<RwLock<System>>,
 }
 
+/// Like [`Response::failed`], but passes on the diagnostics of a script that didn't compile
+fn failed(err: EosError) -> Response {
+    match err {
+        EosError::Compile(diagnostics) => Response::Failed {
+            err: s!("Failed to compile script"),
+            diagnostics,
+        },
+        err => Response::failed(err),
+    }
+}
+
 async fn spawn(State(state): State<Arc<AppState>>, Json(props): Json<Props>) -> Json<Response> {
     Json(match state.sys.write().await.spawn_actor(props).await {
         Ok(id) => {
             _ = teleplot("system.actor.spawned:1");
             Response::Spawned { id }
         }
-        Err(err) => Response::Failed {
-            err: err.to_string(),
-        },
+        Err(err) => failed(err),
     })
 }
 
@@ -202,9 +425,84 @@ async fn list(State(state): State<Arc<AppState>>) -> Json<Response> {
 
 async fn send(State(state): State<Arc<AppState>>, Json(msg): Json<Message>) -> Json<Response> {
     let mut sys = state.sys.write().await;
-    if let Some(actor) = sys.actors.get_mut(&msg.to) {
-        actor.mailbox.push_back(msg);
+    sys.deliver(msg);
+    Json(Response::Done)
+}
+
+async fn ask(
+    State(state): State<Arc<AppState>>,
+    Json(AskRequest {
+        to,
+        payload,
+        timeout_ticks,
+    }): Json<AskRequest>,
+) -> Json<Response> {
+    let reply = {
+        // the system must not stay locked while we wait for the reply
+        let mut sys = state.sys.write().await;
+        if sys.paused {
+            return Json(Response::failed(format!(
+                "The system is paused, {to} can't reply"
+            )));
+        }
+        match sys.ask(&to, payload, timeout_ticks) {
+            Ok(reply) => reply,
+            Err(err) => return Json(Response::failed(err)),
+        }
+    };
+    // the ticks that expire the ask stop coming if the system is paused while we wait
+    let tick = state.config.read().await.tick;
+    let timeout = Duration::from_millis(tick.max(1).saturating_mul(timeout_ticks));
+    Json(match tokio::time::timeout(timeout, reply).await {
+        Ok(Ok(payload)) => Response::Reply { payload },
+        _ => Response::failed(format!("No reply from {to} within {timeout_ticks} ticks")),
+    })
+}
+
+async fn publish(
+    State(state): State<Arc<AppState>>,
+    Json(PublishRequest { topic, payload }): Json<PublishRequest>,
+) -> Json<Response> {
+    if let Err(err) = topics::check_topic(&topic) {
+        return Json(Response::failed(err));
     }
+    let actors = state.sys.write().await.publish(Message {
+        from: None,
+        payload,
+        topic: Some(topic),
+        ..Default::default()
+    });
+    Json(Response::Published { actors })
+}
+
+async fn set_error_policy(
+    State(state): State<Arc<AppState>>,
+    Json(policy): Json<ErrorPolicy>,
+) -> Json<Response> {
+    state.sys.write().await.error_policy = policy;
+    Json(Response::Done)
+}
+
+async fn list_dead_letters(State(state): State<Arc<AppState>>) -> Json<Response> {
+    let dead_letters = state
+        .sys
+        .read()
+        .await
+        .dead_letters
+        .iter()
+        .cloned()
+        .collect();
+    Json(Response::DeadLetters { dead_letters })
+}
+
+async fn replay_dead_letters(State(state): State<Arc<AppState>>) -> Json<Response> {
+    let count = state.sys.write().await.replay_dead_letters();
+    tracing::info!("Replayed {count} dead letters");
+    Json(Response::Done)
+}
+
+async fn clear_dead_letters(State(state): State<Arc<AppState>>) -> Json<Response> {
+    state.sys.write().await.dead_letters.clear();
     Json(Response::Done)
 }
 
@@ -216,7 +514,7 @@ async fn pause(
     if let Some(id) = id
         && let Some(actor) = sys.actors.get_mut(&id)
     {
-        actor.paused = true;
+        actor.set_paused(true);
     } else {
         sys.paused = true;
     }
@@ -231,7 +529,7 @@ async fn unpause(
     if let Some(id) = id
         && let Some(actor) = sys.actors.get_mut(&id)
     {
-        actor.paused = false;
+        actor.set_paused(false);
     } else {
         sys.paused = false;
     }
@@ -239,12 +537,82 @@ async fn unpause(
 }
 
 async fn tick(State(state): State<Arc<AppState>>) -> Json<Response> {
+    let policy = state.config.read().await.policy;
     let mut sys = state.sys.write().await;
+    sys.policy = policy;
     Json(match sys.tick().await {
         Ok(()) => Response::Done,
-        Err(err) => Response::Failed {
-            err: err.to_string(),
-        },
+        Err(err) => Response::failed(err),
+    })
+}
+
+async fn tick_back(State(state): State<Arc<AppState>>, Json(ticks): Json<u64>) -> Json<Response> {
+    Json(match state.sys.write().await.rewind(ticks) {
+        Ok(tick) => Response::Tick { tick },
+        Err(err) => Response::failed(err),
+    })
+}
+
+async fn fork(
+    State(state): State<Arc<AppState>>,
+    Json(ForkRequest { tick, keep }): Json<ForkRequest>,
+) -> Json<Response> {
+    let mut sys = state.sys.write().await;
+    if let Some(name) = keep
+        && let Err(err) = sys.save_snapshot(&name)
+    {
+        return Json(Response::failed(err));
+    }
+    Json(match sys.restore_checkpoint(tick) {
+        Ok(()) => Response::Tick { tick },
+        Err(err) => Response::failed(err),
+    })
+}
+
+async fn reload(
+    State(state): State<Arc<AppState>>,
+    Json(ReloadRequest {
+        actor,
+        script,
+        shared,
+    }): Json<ReloadRequest>,
+) -> Json<Response> {
+    let mut sys = state.sys.write().await;
+    if !shared {
+        return Json(match sys.reload_actor(&actor, &script).await {
+            Ok(()) => Response::Reloaded {
+                actors: vec![actor],
+            },
+            Err(err) => failed(err),
+        });
+    }
+    let results = match sys.reload_shared(&actor, &script).await {
+        Ok(results) => results,
+        Err(err) => {
+            return Json(Response::failed(err));
+        }
+    };
+    let mut actors = Vec::new();
+    let mut errors = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (id, result) in results {
+        match result {
+            Ok(()) => actors.push(id),
+            // every actor got the same script, so they all got the same diagnostics
+            Err(EosError::Compile(found)) => {
+                errors.push(format!("{id}: Failed to compile script"));
+                diagnostics = found;
+            }
+            Err(err) => errors.push(format!("{id}: {err}")),
+        }
+    }
+    Json(if errors.is_empty() {
+        Response::Reloaded { actors }
+    } else {
+        Response::Failed {
+            err: format!("reloaded {actors:?}, but not\n{}", errors.join("\n")),
+            diagnostics,
+        }
     })
 }
 
@@ -260,6 +628,44 @@ async fn reset_tick(State(state): State<Arc<AppState>>) -> Json<Response> {
     Json(Response::Done)
 }
 
+async fn get_policy(State(state): State<Arc<AppState>>) -> Json<Response> {
+    let policy = state.config.read().await.policy;
+    Json(Response::Policy { policy })
+}
+
+async fn set_budget(
+    State(state): State<Arc<AppState>>,
+    Json(messages): Json<usize>,
+) -> Json<Response> {
+    let mut config = state.config.write().await;
+    config.policy.mailbox_budget = messages.max(1);
+    Json(Response::Policy {
+        policy: config.policy,
+    })
+}
+
+async fn set_drain(
+    State(state): State<Arc<AppState>>,
+    Json(enabled): Json<bool>,
+) -> Json<Response> {
+    let mut config = state.config.write().await;
+    config.policy.drain_all = enabled;
+    Json(Response::Policy {
+        policy: config.policy,
+    })
+}
+
+async fn set_global_budget(
+    State(state): State<Arc<AppState>>,
+    Json(messages): Json<Option<usize>>,
+) -> Json<Response> {
+    let mut config = state.config.write().await;
+    config.policy.global_budget = messages.map(|messages| messages.max(1));
+    Json(Response::Policy {
+        policy: config.policy,
+    })
+}
+
 // async fn rename(
 //     State(state): State<Arc<AppState>>,
 //     old: String,
@@ -288,8 +694,109 @@ async fn kill(State(state): State<Arc<AppState>>, Json(ids): Json<Vec<String>>)
     Json(Response::Done)
 }
 
-async fn shutdown() -> Json<Response> {
-    tokio::spawn(async {
+async fn create_group(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<GroupRequest>,
+) -> Json<Response> {
+    Json(match state.sys.write().await.create_group(request).await {
+        Ok(group) => Response::GroupCreated {
+            id: group.id,
+            members: group.members,
+        },
+        Err(err) => failed(err),
+    })
+}
+
+async fn delete_group(
+    State(state): State<Arc<AppState>>,
+    Json(id): Json<String>,
+) -> Json<Response> {
+    Json(match state.sys.write().await.delete_group(&id).await {
+        Ok(()) => Response::Done,
+        Err(err) => Response::failed(err),
+    })
+}
+
+/// Runs `request` on the db `name` and returns what to print
+fn run_db(name: &str, request: DbRequest) -> anyhow::Result<String> {
+    let db = common::Db::new(Path::new(STORAGE), name);
+    Ok(match request {
+        DbRequest::Store { key, value } => {
+            db.store(&key, value)?;
+            String::new()
+        }
+        DbRequest::Delete { key } => {
+            db.delete(&key)?;
+            String::new()
+        }
+        DbRequest::Load { key } => match db.load::<serde_json::Value>(&key)? {
+            Some(value) => serde_json::to_string_pretty(&value)?,
+            None => bail!("Key not found"),
+        },
+        DbRequest::Exists { key } => serde_json::to_string_pretty(&db.exists(&key)?)?,
+        DbRequest::Keys { prefix } => db.keys(&prefix)?.join("\n"),
+        DbRequest::Count => db.count()?.to_string(),
+        DbRequest::Dump { from, to } => {
+            let from = from.as_deref().map_or(Bound::Unbounded, Bound::Included);
+            let to = to.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+            let entries: serde_json::Map<_, _> = db.range((from, to))?.into_iter().collect();
+            serde_json::to_string_pretty(&entries)?
+        }
+        DbRequest::Import {
+            entries,
+            keep_existing,
+        } => {
+            let imported = if keep_existing {
+                db.transaction(|transaction| {
+                    let mut imported = 0;
+                    for (key, value) in entries {
+                        if !transaction.exists(&key)? {
+                            transaction.store(&key, value)?;
+                            imported += 1;
+                        }
+                    }
+                    Ok(imported)
+                })?
+            } else {
+                db.batch(entries)?
+            };
+            format!("Imported {imported} entries")
+        }
+        DbRequest::Compact => {
+            if db.compact()? {
+                s!("Database compacted")
+            } else {
+                s!("No need to compact database")
+            }
+        }
+        DbRequest::Stats => format!("{:#?}", db.stats()?),
+    })
+}
+
+async fn db(Json(DbCall { name, request }): Json<DbCall>) -> Json<Response> {
+    Json(match run_db(&name, request) {
+        Ok(output) => Response::Output { output },
+        Err(err) => Response::failed(err),
+    })
+}
+
+async fn snapshot(State(state): State<Arc<AppState>>, Json(name): Json<String>) -> Json<Response> {
+    Json(match state.sys.read().await.save_snapshot(&name) {
+        Ok(()) => Response::Done,
+        Err(err) => Response::failed(err),
+    })
+}
+
+/// Writes the latest snapshot, so `eos serve --restore latest` continues where we stopped
+async fn snapshot_on_exit(sys: &RwLock<System>) {
+    if let Err(e) = sys.read().await.save_snapshot(LATEST_SNAPSHOT) {
+        tracing::error!("Failed to save snapshot on exit: {e}");
+    }
+}
+
+async fn shutdown(State(state): State<Arc<AppState>>) -> Json<Response> {
+    tokio::spawn(async move {
+        snapshot_on_exit(&state.sys).await;
         tokio::time::sleep(Duration::from_millis(2000)).await;
         if let Err(e) =
             nix::sys::signal::kill(nix::unistd::getpid(), nix::sys::signal::Signal::SIGTERM)
@@ -301,17 +808,7 @@ async fn shutdown() -> Json<Response> {
     Json(Response::Done)
 }
 
-async fn rpc0(endpoint: &str) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
-    let response: Response = serde_json::from_str(
-        &client
-            .post(format!("http://localhost:{EOS_RPC_PORT}/{endpoint}"))
-            .send()
-            .await?
-            .text()
-            .await?,
-    )?;
-
+fn print_response(response: Response) {
     match response {
         Response::Done => {
             tracing::info!("Command executed successfully");
@@ -322,10 +819,142 @@ async fn rpc0(endpoint: &str) -> anyhow::Result<()> {
         Response::Spawned { id } => {
             tracing::info!("Actor spawned with id: {id}");
         }
-        Response::Failed { err } => {
-            tracing::error!("Failed: {err}")
+        Response::Reloaded { actors } => {
+            tracing::info!("Reloaded actors: {:?}", actors);
+        }
+        Response::Policy {
+            policy:
+                TickPolicy {
+                    mailbox_budget,
+                    drain_all,
+                    global_budget,
+                },
+        } => {
+            let global_budget = global_budget
+                .map(|budget| budget.to_string())
+                .unwrap_or_else(|| s!("unlimited"));
+            tracing::info!(
+                "Tick policy: mailbox budget: {mailbox_budget}, drain all: {drain_all}, global budget: {global_budget}"
+            );
+        }
+        Response::DeadLetters { dead_letters } => match serde_json::to_string_pretty(&dead_letters)
+        {
+            Ok(dead_letters) => println!("{dead_letters}"),
+            Err(err) => tracing::error!("Failed to format dead letters: {err}"),
+        },
+        Response::Reply { payload } => match serde_json::to_string_pretty(&payload) {
+            Ok(payload) => println!("{payload}"),
+            Err(err) => tracing::error!("Failed to format reply: {err}"),
+        },
+        Response::GroupCreated { id, members } => {
+            tracing::info!("Group {id} created with members: {:?}", members);
+        }
+        Response::Published { actors } => {
+            tracing::info!("Published to {} actors: {:?}", actors.len(), actors);
+        }
+        Response::Tick { tick } => {
+            tracing::info!("System is at tick {tick}");
+        }
+        Response::Output { output } => {
+            if !output.is_empty() {
+                println!("{output}");
+            }
+        }
+        Response::Failed { err, diagnostics } => {
+            tracing::error!("Failed: {err}");
+            let color = if std::io::stderr().is_terminal() {
+                ColorChoice::Auto
+            } else {
+                ColorChoice::Never
+            };
+            let mut stderr = StandardStream::stderr(color);
+            for diagnostic in diagnostics {
+                if let Err(e) = diagnostic.emit(&mut stderr) {
+                    tracing::error!("Failed to print diagnostic: {e}");
+                }
+            }
         }
     }
+}
+
+async fn replay(Json(request): Json<ReplayRequest>) -> Json<Response> {
+    Json(
+        match tokio::task::spawn_blocking(move || run_replay(request)).await {
+            Ok(Ok(output)) => Response::Output { output },
+            Ok(Err(err)) => Response::failed(err),
+            Err(err) => Response::failed(err),
+        },
+    )
+}
+
+/// Replays a journal as `request` says and returns the replayed states to print
+///
+/// The replay runs on a thread of its own, since the ids and dbs replayed scripts use
+/// are set up per thread and must not leak into the ticks of a running server.
+fn run_replay(
+    ReplayRequest {
+        journal,
+        until_tick,
+        save,
+    }: ReplayRequest,
+) -> anyhow::Result<String> {
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let Replay {
+            system,
+            divergences,
+        } = runtime.block_on(journal::replay(&journal, until_tick))?;
+        if let Some(name) = save {
+            system.save_snapshot(&name)?;
+        }
+        let actors: BTreeMap<_, _> = system
+            .actors
+            .iter()
+            .map(|(id, actor)| (id, &actor.state))
+            .collect();
+        Ok(serde_json::to_string_pretty(&serde_json::json!({
+            "tick": system.current_tick,
+            "actors": actors,
+            "divergences": divergences,
+        }))?)
+    })
+    .join()
+    .map_err(|_| anyhow::anyhow!("Replay panicked"))?
+}
+
+/// The `script` and `file` to spawn an actor with from the `script` argument
+///
+/// The source of the file is sent, unless `file` is set. Then an existing file is sent by
+/// its absolute path, anything else as the name of a script in the server's script path.
+async fn script_source(script: &Path, file: bool) -> anyhow::Result<(String, Option<String>)> {
+    let path = PathBuf::from(shellexpand::full(&script.display().to_string())?.to_string());
+    Ok(if !file {
+        (tokio::fs::read_to_string(path).await?, None)
+    } else if path.exists() {
+        // the server resolves relative names against its script path instead
+        (
+            String::new(),
+            Some(path.canonicalize()?.display().to_string()),
+        )
+    } else {
+        (String::new(), Some(script.display().to_string()))
+    })
+}
+
+async fn rpc0(endpoint: &str) -> anyhow::Result<()> {
+    let client = reqwest::Client::new();
+    let response: Response = serde_json::from_str(
+        &client
+            .post(format!("http://localhost:{EOS_RPC_PORT}/{endpoint}"))
+            .send()
+            .await?
+            .text()
+            .await?,
+    )?;
+
+    print_response(response);
     Ok(())
 }
 
@@ -341,25 +970,13 @@ async fn rpc<T: Serialize>(endpoint: &str, data: &T) -> anyhow::Result<()> {
             .await?,
     )?;
 
-    match response {
-        Response::Done => {
-            tracing::info!("Command executed successfully");
-        }
-        Response::Actors { actors } => {
-            tracing::info!("Actors: {:?}", actors);
-        }
-        Response::Spawned { id } => {
-            tracing::info!("Actor spawned with id: {id}");
-        }
-        Response::Failed { err } => {
-            tracing::error!("Failed: {err}")
-        }
-    }
+    print_response(response);
     Ok(())
 }
 
 struct Config {
     tick: u64,
+    policy: TickPolicy,
 }
 
 struct OptionDropper<T>(Option<T>);
@@ -374,11 +991,6 @@ impl<T> Drop for OptionDropper<T> {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tokio::spawn(async {
-        tokio::signal::ctrl_c().await.unwrap();
-        std::process::exit(0);
-    });
-
     #[cfg(feature = "_setup")]
     {
         let SetupCli { out_dir } = SetupCli::parse();
@@ -389,9 +1001,16 @@ async fn main() -> anyhow::Result<()> {
 
     let Cli { command } = Cli::parse();
 
+    if !command.is_serve() {
+        tokio::spawn(async {
+            tokio::signal::ctrl_c().await.unwrap();
+            std::process::exit(0);
+        });
+    }
+
     let _log_guard = if command.is_serve() {
         let logs = LOGS;
-        if !std::fs::exists(&logs)? {
+        if !std::fs::exists(logs)? {
             std::fs::create_dir_all(logs)?;
         }
         let file_appender = tracing_appender::rolling::hourly(LOGS, "eos.log");
@@ -407,44 +1026,120 @@ async fn main() -> anyhow::Result<()> {
 
     match command {
         Action::Db { name, command } => {
-            let storage = Path::new(STORAGE);
-            let db = common::Db::new(&storage, &name);
-            match command {
-                DbCommand::Store { key, value } => {
-                    db.store(&key, serde_json::to_value(value)?)?;
-                }
-                DbCommand::Delete { key } => {
-                    db.delete(&key)?;
-                }
-                DbCommand::Load { key } => {
-                    let value = db.load::<serde_json::Value>(&key)?;
-                    match value {
-                        Some(value) => println!("{}", serde_json::to_string_pretty(&value)?),
-                        None => bail!("Key not found"),
-                    }
-                }
-                DbCommand::Exists { key } => {
-                    println!("{}", serde_json::to_string_pretty(&db.exists(&key)?)?);
-                }
-                DbCommand::Compact => {
-                    if db.compact()? {
-                        println!("Database compacted");
+            let request = match command {
+                DbCommand::Store { key, value } => DbRequest::Store {
+                    key,
+                    value: serde_json::to_value(value)?,
+                },
+                DbCommand::Delete { key } => DbRequest::Delete { key },
+                DbCommand::Load { key } => DbRequest::Load { key },
+                DbCommand::Exists { key } => DbRequest::Exists { key },
+                DbCommand::Keys { prefix } => DbRequest::Keys { prefix },
+                DbCommand::Count => DbRequest::Count,
+                DbCommand::Dump { from, to } => DbRequest::Dump { from, to },
+                DbCommand::Import {
+                    file,
+                    keep_existing,
+                } => {
+                    let content = if file == Path::new("-") {
+                        std::io::read_to_string(std::io::stdin())?
                     } else {
-                        println!("No need to compact database");
+                        std::fs::read_to_string(file)?
+                    };
+                    DbRequest::Import {
+                        entries: serde_json::from_str(&content)?,
+                        keep_existing,
                     }
                 }
-                DbCommand::Stats => {
-                    println!("{:#?}", db.stats()?);
+                DbCommand::Compact => DbRequest::Compact,
+                DbCommand::Stats => DbRequest::Stats,
+            };
+            let call = DbCall { name, request };
+            // a running server holds the db open, so it has to do the work
+            match reqwest::Client::new()
+                .post(format!("http://localhost:{EOS_RPC_PORT}/db"))
+                .json(&call)
+                .send()
+                .await
+            {
+                Ok(response) => print_response(serde_json::from_str(&response.text().await?)?),
+                Err(e) if e.is_connect() => {
+                    let output = run_db(&call.name, call.request)?;
+                    if !output.is_empty() {
+                        println!("{output}");
+                    }
                 }
+                Err(e) => return Err(e.into()),
             }
         }
-        Action::Spawn { id, script } => {
-            let script = tokio::fs::read_to_string(PathBuf::from(
-                shellexpand::full(&script.display().to_string())?.to_string(),
-            ))
+        Action::Spawn {
+            id,
+            supervisor,
+            strategy,
+            max_restarts,
+            within,
+            file,
+            script,
+        } => {
+            let supervisor = match supervisor {
+                Some(p) => Some(
+                    p.file_name()
+                        .ok_or_else(|| anyhow::anyhow!("Invalid path: no file name found"))?
+                        .display()
+                        .to_string(),
+                ),
+                None => None,
+            };
+            let (script, file) = script_source(&script, file).await?;
+            rpc(
+                "spawn",
+                &Props {
+                    id,
+                    script,
+                    file,
+                    supervisor,
+                    supervision: SupervisorSpec {
+                        strategy,
+                        max_restarts,
+                        within_ticks: within,
+                    },
+                    ..Default::default()
+                },
+            )
             .await?;
-            rpc("spawn", &Props { id, script }).await?;
         }
+        Action::Group { command } => match command {
+            GroupCommand::Create {
+                id,
+                script,
+                file,
+                size,
+                routing,
+                hash_key,
+            } => {
+                let (script, file) = script_source(&script, file).await?;
+                rpc(
+                    "group/create",
+                    &GroupRequest {
+                        id,
+                        script,
+                        file,
+                        size,
+                        routing,
+                        hash_key,
+                    },
+                )
+                .await?;
+            }
+            GroupCommand::Delete { path } => {
+                let id = path
+                    .file_name()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid path: no file name found"))?
+                    .display()
+                    .to_string();
+                rpc("group/delete", &id).await?;
+            }
+        },
         Action::List => rpc0("list").await?,
         Action::Send { path, msg, sender } => {
             let id = path
@@ -458,9 +1153,60 @@ async fn main() -> anyhow::Result<()> {
                 from: sender,
                 to: id,
                 payload: serde_json::from_str(&msg)?,
+                ..Default::default()
             };
             rpc("send", &msg).await?;
         }
+        Action::Ask { timeout, path, msg } => {
+            let to = path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Invalid path: no file name found"))?
+                .display()
+                .to_string();
+            rpc(
+                "ask",
+                &AskRequest {
+                    to,
+                    payload: serde_json::from_str(&msg)?,
+                    timeout_ticks: timeout,
+                },
+            )
+            .await?;
+        }
+        Action::Publish { topic, msg } => {
+            rpc(
+                "publish",
+                &PublishRequest {
+                    topic,
+                    payload: serde_json::from_str(&msg)?,
+                },
+            )
+            .await?;
+        }
+        Action::Reload {
+            shared,
+            path,
+            script,
+        } => {
+            let actor = path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Invalid path: no file name found"))?
+                .display()
+                .to_string();
+            let script = tokio::fs::read_to_string(PathBuf::from(
+                shellexpand::full(&script.display().to_string())?.to_string(),
+            ))
+            .await?;
+            rpc(
+                "reload",
+                &ReloadRequest {
+                    actor,
+                    script,
+                    shared,
+                },
+            )
+            .await?;
+        }
         Action::Kill { paths } => {
             let ids: Result<Vec<String>, _> = paths
                 .iter()
@@ -503,27 +1249,134 @@ async fn main() -> anyhow::Result<()> {
             TickCommand::Now => {
                 rpc0("tick/now").await?;
             }
+            TickCommand::Back { ticks } => {
+                rpc("tick/back", &ticks).await?;
+            }
             TickCommand::Reset => {
                 rpc0("tick/reset").await?;
             }
             TickCommand::Set { milliseconds } => {
                 rpc("tick/set", &milliseconds).await?;
             }
+            TickCommand::Policy => {
+                rpc0("tick/policy").await?;
+            }
+            TickCommand::Budget { messages } => {
+                rpc("tick/budget", &messages).await?;
+            }
+            TickCommand::Drain { enabled } => {
+                rpc("tick/drain", &enabled).await?;
+            }
+            TickCommand::GlobalBudget { messages } => {
+                rpc("tick/global_budget", &messages).await?;
+            }
+        },
+        Action::DeadLetters { command } => match command {
+            DeadLetterCommand::List => {
+                rpc0("dead_letters/list").await?;
+            }
+            DeadLetterCommand::Replay => {
+                rpc0("dead_letters/replay").await?;
+            }
+            DeadLetterCommand::Clear => {
+                rpc0("dead_letters/clear").await?;
+            }
         },
+        Action::OnError { policy } => {
+            let policy = match policy {
+                ErrorPolicyCommand::Pause => ErrorPolicy::Pause,
+                ErrorPolicyCommand::Drop => ErrorPolicy::Drop,
+                ErrorPolicyCommand::Retry { times } => ErrorPolicy::Retry { times },
+            };
+            rpc("error_policy", &policy).await?;
+        }
         Action::Plot { value } => {
             common::teleplot(&value)?;
         }
         Action::Shutdown => {
             rpc0("shutdown").await?;
         }
-        Action::Serve { mount, endpoint } => {
-            tokio::spawn(async {
-                tokio::signal::ctrl_c().await.unwrap();
-                std::process::exit(0);
-            });
+        Action::Snapshot { name } => {
+            rpc("snapshot", &name).await?;
+        }
+        Action::Fork { tick, keep } => {
+            rpc("fork", &ForkRequest { tick, keep }).await?;
+        }
+        Action::Replay {
+            journal,
+            until_tick,
+            save,
+        } => {
+            let request = ReplayRequest {
+                journal,
+                until_tick,
+                save,
+            };
+            // a running server holds the journal and the snapshots open, so it has to do the work
+            match reqwest::Client::new()
+                .post(format!("http://localhost:{EOS_RPC_PORT}/replay"))
+                .json(&request)
+                .send()
+                .await
+            {
+                Ok(response) => print_response(serde_json::from_str(&response.text().await?)?),
+                Err(e) if e.is_connect() => {
+                    println!(
+                        "{}",
+                        tokio::task::spawn_blocking(move || run_replay(request)).await??
+                    );
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Action::Serve {
+            mount,
+            endpoint,
+            restore,
+            snapshot_interval,
+            journal,
+            script_path,
+        } => {
+            scripts::set_script_path(script_path);
+            let mut sys = match restore {
+                Some(name) => {
+                    let sys = System::load_snapshot(&name)?
+                        .ok_or_else(|| anyhow::anyhow!("Snapshot {name:?} not found"))?;
+                    tracing::info!("Restored snapshot {name:?} at tick {}", sys.current_tick);
+                    sys
+                }
+                None => System::new(),
+            };
+            if let Some(name) = journal {
+                sys.journal = Some(Journal::start(&name, &sys)?);
+            }
+            sys.checkpoint()?;
+            let config = Arc::new(RwLock::new(Config {
+                tick: DEFAULT_TICK,
+                policy: sys.policy,
+            }));
+            let sys = Arc::new(RwLock::new(sys));
 
-            let config = Arc::new(RwLock::new(Config { tick: DEFAULT_TICK }));
-            let sys = Arc::new(RwLock::new(System::new()));
+            {
+                let sys = sys.clone();
+                tokio::spawn(async move {
+                    tokio::signal::ctrl_c().await.unwrap();
+                    snapshot_on_exit(&sys).await;
+                    std::process::exit(0);
+                });
+            }
+
+            if snapshot_interval > 0 {
+                let sys = sys.clone();
+                tokio::spawn(async move {
+                    loop {
+                        tokio::time::sleep(Duration::from_secs(snapshot_interval)).await;
+                        if let Err(e) = sys.read().await.save_snapshot(LATEST_SNAPSHOT) {
+                            tracing::error!("Failed to save snapshot: {e}");
+                        }
+                    }
+                });
+            }
 
             {
                 let endpoint = endpoint.clone();
@@ -535,11 +1388,13 @@ async fn main() -> anyhow::Result<()> {
             }
 
             {
+                let sys = sys.clone();
                 tokio::spawn(async move {
                     loop {
                         tokio::time::sleep(Duration::from_secs(2)).await;
                         if tokio::fs::try_exists(KILL_FILE).await.unwrap() {
                             tokio::fs::remove_file(KILL_FILE).await.unwrap();
+                            snapshot_on_exit(&sys).await;
                             std::process::exit(0);
                         }
                     }
@@ -553,7 +1408,10 @@ async fn main() -> anyhow::Result<()> {
                     loop {
                         let tick = config.read().await.tick;
                         tokio::time::sleep(Duration::from_millis(tick)).await;
-                        if let Err(e) = sys.write().await.tick().await {
+                        let policy = config.read().await.policy;
+                        let mut sys = sys.write().await;
+                        sys.policy = policy;
+                        if let Err(e) = sys.tick().await {
                             tracing::error!("Failed to tick: {e}");
                         }
                     }
@@ -569,11 +1427,10 @@ async fn main() -> anyhow::Result<()> {
                                 .arg("umount")
                                 .arg(&endpoint)
                                 .spawn()
+                                && c.wait().await.is_ok()
                             {
-                                if let Ok(_) = c.wait().await {
-                                    tracing::info!("Successfully unmounted previous 9p filesystem");
-                                    break;
-                                }
+                                tracing::info!("Successfully unmounted previous 9p filesystem");
+                                break;
                             }
                         }
                         tracing::info!("Mounting 9p filesystem utilizing sudo");
@@ -592,7 +1449,7 @@ async fn main() -> anyhow::Result<()> {
                                             "version=9p2000.L,trans=tcp,port={port},uname={}",
                                             std::env::var("USER").unwrap_or_else(|_| s!("vscode"))
                                         ))
-                                        .arg(format!("{}", addr))
+                                        .arg(addr)
                                         .arg(&mount)
                                         .spawn()
                                     {
@@ -652,14 +1509,32 @@ async fn main() -> anyhow::Result<()> {
                 let app = Router::new()
                     .route("/spawn", post(spawn))
                     .route("/send", post(send))
+                    .route("/ask", post(ask))
+                    .route("/publish", post(publish))
                     .route("/pause", post(pause))
                     .route("/unpause", post(unpause))
                     .route("/tick/now", post(tick))
+                    .route("/tick/back", post(tick_back))
                     .route("/tick/reset", post(reset_tick))
                     .route("/tick/set", post(set_tick))
+                    .route("/tick/policy", post(get_policy))
+                    .route("/tick/budget", post(set_budget))
+                    .route("/tick/drain", post(set_drain))
+                    .route("/tick/global_budget", post(set_global_budget))
                     .route("/list", post(list))
+                    .route("/error_policy", post(set_error_policy))
+                    .route("/dead_letters/list", post(list_dead_letters))
+                    .route("/dead_letters/replay", post(replay_dead_letters))
+                    .route("/dead_letters/clear", post(clear_dead_letters))
                     .route("/kill", post(kill))
+                    .route("/group/create", post(create_group))
+                    .route("/group/delete", post(delete_group))
                     .route("/shutdown", post(shutdown))
+                    .route("/snapshot", post(snapshot))
+                    .route("/fork", post(fork))
+                    .route("/reload", post(reload))
+                    .route("/db", post(db))
+                    .route("/replay", post(replay))
                     .with_state(state);
 
                 tracing::info!("RPC server listening on port {}", EOS_RPC_PORT);
@@ -0,0 +1,791 @@
+//! Compiled script cache and the host functions exposed to actor scripts
+//!
+//! Compiling a Rune script is by far the most expensive part of running an actor,
+//! so compiled units are cached by their source and shared between all actors
+//! running the same script. The host module is installed into a single
+//! [`Context`] once; per-actor data (the calling actor's id and the side effects it
+//! requests) is bound around each call through [`bind`] instead of being captured
+//! by the host functions, so units never have to be recompiled per actor.
+//!
+//! Scripts are either inline sources carried by the actor itself, or files looked
+//! up in the script path set with [`set_script_path`]. Files are compiled from disk,
+//! so they can split their code into modules with `mod name;`, which loads `name.rn`
+//! or `name/mod.rn` next to the file declaring it.
+//!
+//! Host functions never touch the [`System`](crate::system::System) directly. They
+//! only record [`Effects`], which the system applies after the call returns, so
+//! scripts can run while the caller holds the system lock.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use nanoid::nanoid;
+use rune::{
+    Context, Diagnostics, Module, Source, SourceId, Sources, Vm,
+    ast::Spanned,
+    diagnostics::FatalDiagnosticKind,
+    from_value,
+    runtime::{InstAddress, Memory, Output, RuntimeContext, Unit, VmError, VmResult},
+    termcolor::NoColor,
+    vm_try,
+};
+
+use crate::{
+    common::{
+        Db, Diagnostic, Message, Props, Severity, Span,
+        dirs::{ACTOR_DBS, STORAGE},
+        fnv1a, format_diagnostics, teleplot,
+    },
+    system::{EosError, EosResult},
+    timers::{Delay, Timer},
+    topics,
+};
+
+thread_local! {
+    /// The actor whose script is currently executing on this thread
+    static BINDING: RefCell<Option<Binding>> = const { RefCell::new(None) };
+    /// ids `spawn`, `ask` and the timers generated since they were last taken, so a journal
+    /// can record them
+    static GENERATED_IDS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    /// ids `spawn`, `ask` and the timers hand out instead of generating new ones while a
+    /// journal is replayed
+    static REPLAYED_IDS: RefCell<VecDeque<String>> = const { RefCell::new(VecDeque::new()) };
+    /// Directory with copies of the actor dbs the db functions use instead of the dbs
+    /// themselves
+    static SCRATCH_DBS: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Directories library scripts are looked up in, in order
+static SCRIPT_PATH: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+
+/// Sets the directories [`find_script`] looks in
+pub fn set_script_path(dirs: Vec<PathBuf>) {
+    *SCRIPT_PATH.write().unwrap() = dirs;
+}
+
+/// Makes the db functions on this thread use copies of the actor dbs in `dir`, or the
+/// dbs again with `None`
+///
+/// Used when replaying a journal, so replayed scripts can't change the live dbs. The db
+/// of an actor is copied into `dir` the first time its script uses it.
+pub fn set_scratch_dbs(dir: Option<PathBuf>) {
+    SCRATCH_DBS.replace(dir);
+}
+
+/// Finds the entry file of the library script `name`
+///
+/// Absolute paths are used as they are, anything else is looked up in each directory
+/// of the script path. A name matches the file `name`, `name.rn` or, for scripts made
+/// of several files, `name/main.rn`.
+pub fn find_script(name: &str) -> Option<PathBuf> {
+    let name = PathBuf::from(shellexpand::full(name).ok()?.to_string());
+    let candidates = |path: PathBuf| {
+        [
+            path.clone(),
+            path.with_added_extension("rn"),
+            path.join("main.rn"),
+        ]
+    };
+    if name.is_absolute() {
+        return candidates(name).into_iter().find(|path| path.is_file());
+    }
+    SCRIPT_PATH
+        .read()
+        .unwrap()
+        .iter()
+        .flat_map(|dir| candidates(dir.join(&name)))
+        .find(|path| path.is_file())
+}
+
+/// The code an actor runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScriptRef<'a> {
+    /// source carried by the actor itself
+    Inline(&'a str),
+    /// a library script, see [`find_script`]
+    File(&'a str),
+}
+
+impl<'a> ScriptRef<'a> {
+    /// Name of the script in `/scripts`, the file name or the hash of an inline source
+    pub fn name(&self) -> String {
+        match self {
+            ScriptRef::Inline(_) => format!("{:016x}", script_hash(*self)),
+            ScriptRef::File(name) => name.to_string(),
+        }
+    }
+
+    /// The source of the script, or of the entry file of a library script
+    pub fn source(&self) -> EosResult<Cow<'a, str>> {
+        match self {
+            ScriptRef::Inline(source) => Ok(Cow::Borrowed(source)),
+            ScriptRef::File(name) => {
+                let path =
+                    find_script(name).ok_or_else(|| EosError::UnknownScript(name.to_string()))?;
+                Ok(Cow::Owned(std::fs::read_to_string(path)?))
+            }
+        }
+    }
+}
+
+/// Side effects requested by a script through host functions while it runs
+#[derive(Debug, Default)]
+pub struct Effects {
+    /// messages sent by the script, in the order they were sent
+    pub outbox: Vec<Message>,
+    pub spawns: Vec<Props>,
+    /// correlation ids of the requests made with `ask`, with their timeout in ticks
+    pub asks: Vec<(String, u64)>,
+    /// timers started with `send_after` and `schedule_every`
+    pub timers: Vec<Timer>,
+    /// ids of the timers stopped with `cancel_timer`
+    pub cancelled_timers: Vec<String>,
+    /// patterns passed to `subscribe` (true) and `unsubscribe` (false), in that order
+    pub subscriptions: Vec<(String, bool)>,
+    pub lifecycle: Lifecycle,
+}
+
+/// The `watch`, `link`, `trap_exit` and `exit` calls of a script
+#[derive(Debug, Default)]
+pub struct Lifecycle {
+    /// actors passed to `watch` (true) and `unwatch` (false), in that order
+    pub watches: Vec<(String, bool)>,
+    /// actors passed to `link` (true) and `unlink` (false), in that order
+    pub links: Vec<(String, bool)>,
+    /// the last value passed to `trap_exit`
+    pub trap_exit: Option<bool>,
+    /// whether the script called `exit`
+    pub exit: bool,
+}
+
+#[derive(Debug)]
+struct Binding {
+    id: String,
+    effects: Effects,
+}
+
+/// Runs `f` with the host functions bound to the actor `id`
+///
+/// Returns the result of `f` together with the side effects the script requested.
+pub fn bind<T>(id: &str, f: impl FnOnce() -> T) -> (T, Effects) {
+    let previous = BINDING.replace(Some(Binding {
+        id: id.to_owned(),
+        effects: Effects::default(),
+    }));
+    let result = f();
+    let binding = BINDING.replace(previous);
+    (result, binding.map(|b| b.effects).unwrap_or_default())
+}
+
+fn with_binding<T>(f: impl FnOnce(&mut Binding) -> T) -> Option<T> {
+    BINDING.with_borrow_mut(|binding| binding.as_mut().map(f))
+}
+
+/// Returns the ids `spawn`, `ask` and the timers generated on this thread since the last call
+pub fn take_generated_ids() -> Vec<String> {
+    GENERATED_IDS.take()
+}
+
+/// Makes `spawn`, `ask` and the timers hand out `ids` before they generate new ones again
+///
+/// Used when replaying a journal, so spawned children and requests get the ids they
+/// had back then.
+pub fn replay_ids(ids: Vec<String>) {
+    REPLAYED_IDS.replace(ids.into());
+}
+
+fn generate_id() -> String {
+    let id = REPLAYED_IDS
+        .with_borrow_mut(VecDeque::pop_front)
+        .unwrap_or_else(|| nanoid!());
+    GENERATED_IDS.with_borrow_mut(|ids| ids.push(id.clone()));
+    id
+}
+
+/// Hash identifying a script in the [`ScriptCache`]
+///
+/// It is the same across builds, so the names derived from it are as well.
+pub fn script_hash(script: ScriptRef) -> u64 {
+    let (kind, text) = match script {
+        ScriptRef::Inline(source) => (0, source),
+        ScriptRef::File(name) => (1, name),
+    };
+    fnv1a(std::iter::once(kind).chain(text.bytes()))
+}
+
+/// A compiled unit together with the sources it was compiled from
+struct Compiled {
+    unit: Arc<Unit>,
+    sources: Sources,
+    /// the warnings of the compiler
+    diagnostics: Vec<Diagnostic>,
+    /// the files the unit was compiled from with their mtimes, empty for inline sources
+    modified: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Compiled {
+    /// Whether one of the files the unit was compiled from changed since
+    fn is_stale(&self) -> bool {
+        self.modified
+            .iter()
+            .any(|(path, modified)| mtime(path) != *modified)
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Compiled units shared by all actors
+#[derive(Default)]
+pub struct ScriptCache {
+    context: Option<(Arc<Context>, Arc<RuntimeContext>)>,
+    /// units of inline scripts, by their source
+    inline: HashMap<String, Compiled>,
+    /// units of library scripts, by name
+    library: HashMap<String, Compiled>,
+}
+
+impl std::fmt::Debug for ScriptCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScriptCache")
+            .field("inline", &self.inline.len())
+            .field("library", &self.library.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl ScriptCache {
+    /// Creates a VM for `script`, compiling it only if it isn't cached yet
+    ///
+    /// Library scripts are compiled again once one of their files changed on disk, so
+    /// the actors running them continue with the edited code.
+    pub fn vm(&mut self, script: ScriptRef) -> EosResult<Vm> {
+        let (context, runtime) = self.context()?;
+        let cached = self
+            .get(script)
+            .filter(|compiled| !compiled.is_stale())
+            .map(|compiled| compiled.unit.clone());
+        let unit = match cached {
+            Some(unit) => unit,
+            None => {
+                log::debug!("compiling script {}", script.name());
+                let compiled = compile(&context, script)?;
+                let unit = compiled.unit.clone();
+                match script {
+                    ScriptRef::Inline(source) => self.inline.insert(source.to_owned(), compiled),
+                    ScriptRef::File(name) => self.library.insert(name.to_owned(), compiled),
+                };
+                unit
+            }
+        };
+        Ok(Vm::new(runtime, unit))
+    }
+
+    fn get(&self, script: ScriptRef) -> Option<&Compiled> {
+        match script {
+            ScriptRef::Inline(source) => self.inline.get(source),
+            ScriptRef::File(name) => self.library.get(name),
+        }
+    }
+
+    /// Renders the stack trace of an error raised while running `script`
+    ///
+    /// Returns an empty string if `script` is not compiled (anymore).
+    pub fn backtrace(&self, script: ScriptRef, error: &VmError) -> String {
+        let Some(compiled) = self.get(script) else {
+            return String::new();
+        };
+        let mut out = NoColor::new(Vec::new());
+        if let Err(e) = error.emit(&mut out, &compiled.sources) {
+            log::error!("Failed to render backtrace: {e}");
+        }
+        String::from_utf8_lossy(&out.into_inner()).into_owned()
+    }
+
+    /// Names of the files `script` was compiled from, empty if it isn't compiled
+    pub fn files(&self, script: ScriptRef) -> Vec<String> {
+        let Some(compiled) = self.get(script) else {
+            return Vec::new();
+        };
+        (0..)
+            .map_while(|index| compiled.sources.get(SourceId::new(index)))
+            .filter_map(|source| Some(source.path()?.display().to_string()))
+            .collect()
+    }
+
+    /// The warnings `script` was compiled with, empty if it isn't compiled
+    pub fn diagnostics(&self, script: ScriptRef) -> &[Diagnostic] {
+        self.get(script)
+            .map_or(&[], |compiled| &compiled.diagnostics)
+    }
+
+    /// Drops every cached unit whose script is not in `in_use`
+    pub fn retain(&mut self, in_use: &HashSet<ScriptRef>) {
+        self.inline
+            .retain(|source, _| in_use.contains(&ScriptRef::Inline(source)));
+        self.library
+            .retain(|name, _| in_use.contains(&ScriptRef::File(name)));
+    }
+
+    fn context(&mut self) -> EosResult<(Arc<Context>, Arc<RuntimeContext>)> {
+        if let Some(context) = &self.context {
+            return Ok(context.clone());
+        }
+        let mut context = Context::with_default_modules()?;
+        context.install(host_module()?)?;
+        let runtime = Arc::new(context.runtime()?);
+        let context = (Arc::new(context), runtime);
+        self.context = Some(context.clone());
+        Ok(context)
+    }
+}
+
+fn compile(context: &Context, script: ScriptRef) -> EosResult<Compiled> {
+    let mut sources = Sources::new();
+    match script {
+        ScriptRef::Inline(source) => sources.insert(Source::memory(source)?)?,
+        ScriptRef::File(name) => {
+            let path = find_script(name).ok_or_else(|| EosError::UnknownScript(name.to_owned()))?;
+            sources.insert(Source::from_path(path)?)?
+        }
+    };
+
+    let mut diagnostics = Diagnostics::new();
+
+    let result = rune::prepare(&mut sources)
+        .with_context(context)
+        .with_diagnostics(&mut diagnostics)
+        .build();
+
+    let inline = match script {
+        ScriptRef::Inline(source) => Some(source),
+        ScriptRef::File(_) => None,
+    };
+    let diagnostics = to_diagnostics(&diagnostics, &sources, inline);
+    let Ok(unit) = result else {
+        return Err(EosError::Compile(diagnostics));
+    };
+    if !diagnostics.is_empty() {
+        log::warn!(
+            "script {} compiled with warnings\n{}",
+            script.name(),
+            format_diagnostics(&diagnostics)
+        );
+    }
+    let modified = (0..)
+        .map_while(|index| sources.get(SourceId::new(index)))
+        .filter_map(|source| source.path())
+        .map(|path| (path.to_owned(), mtime(path)))
+        .collect();
+    Ok(Compiled {
+        unit: Arc::new(unit),
+        sources,
+        diagnostics,
+        modified,
+    })
+}
+
+/// Turns what the compiler reported into [`Diagnostic`]s
+///
+/// `inline` is the text of a source that isn't read from a file.
+fn to_diagnostics(
+    diagnostics: &Diagnostics,
+    sources: &Sources,
+    inline: Option<&str>,
+) -> Vec<Diagnostic> {
+    diagnostics
+        .diagnostics()
+        .iter()
+        .filter_map(|diagnostic| {
+            let (severity, source_id, span, message) = match diagnostic {
+                rune::diagnostics::Diagnostic::Fatal(fatal) => {
+                    let span = match fatal.kind() {
+                        FatalDiagnosticKind::CompileError(error) => Some(error.span()),
+                        _ => None,
+                    };
+                    (Severity::Error, fatal.source_id(), span, fatal.to_string())
+                }
+                rune::diagnostics::Diagnostic::Warning(warning) => (
+                    Severity::Warning,
+                    warning.source_id(),
+                    Some(warning.span()),
+                    warning.to_string(),
+                ),
+                // raised while running, so there is nothing to point at in the source
+                _ => return None,
+            };
+            let source = sources.get(source_id)?;
+            let text = match source.path() {
+                Some(path) => std::fs::read_to_string(path).unwrap_or_default(),
+                None => inline.unwrap_or_default().to_owned(),
+            };
+            let span = span.map(|span| {
+                let (line, column) = source.pos_to_utf8_linecol(span.range().start);
+                let (end_line, end_column) = source.pos_to_utf8_linecol(span.range().end);
+                Span {
+                    line: line + 1,
+                    column: column + 1,
+                    end_line: end_line + 1,
+                    end_column: end_column + 1,
+                }
+            });
+            let code = span
+                .and_then(|span| text.lines().nth(span.line - 1))
+                .unwrap_or_default()
+                .to_owned();
+            Some(Diagnostic {
+                severity,
+                file: source.name().to_owned(),
+                span,
+                message,
+                code,
+            })
+        })
+        .collect()
+}
+
+fn host_module() -> EosResult<Module> {
+    let mut m = Module::new();
+    m.function("send", |to: &str, value: rune::Value| {
+        let payload = match serde_json::to_value(value) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::error!("Failed to serialize message payload: {}", e);
+                return;
+            }
+        };
+        let sent = with_binding(|binding| {
+            binding.effects.outbox.push(Message {
+                from: Some(binding.id.clone()),
+                to: to.to_owned(),
+                payload,
+                ..Default::default()
+            })
+        });
+        if sent.is_none() {
+            log::error!("send called outside of an actor");
+        }
+    })
+    .build()?;
+    m.function("ask", ask).build()?;
+    m.function(
+        "send_after",
+        |to: &str, value: rune::Value, delay: rune::Value| start_timer(to, value, delay, false),
+    )
+    .build()?;
+    m.function(
+        "schedule_every",
+        |to: &str, value: rune::Value, interval: rune::Value| {
+            start_timer(to, value, interval, true)
+        },
+    )
+    .build()?;
+    m.function("cancel_timer", |id: &str| -> VmResult<()> {
+        let cancelled =
+            with_binding(|binding| binding.effects.cancelled_timers.push(id.to_owned()));
+        if cancelled.is_none() {
+            return VmResult::panic("cancel_timer called outside of an actor");
+        }
+        VmResult::Ok(())
+    })
+    .build()?;
+    m.function("publish", publish).build()?;
+    m.function("subscribe", |pattern: &str| {
+        update_subscription(pattern, true)
+    })
+    .build()?;
+    m.function("unsubscribe", |pattern: &str| {
+        update_subscription(pattern, false)
+    })
+    .build()?;
+    m.function("watch", |id: &str| {
+        update_lifecycle("watch", |lifecycle| {
+            lifecycle.watches.push((id.to_owned(), true))
+        })
+    })
+    .build()?;
+    m.function("unwatch", |id: &str| {
+        update_lifecycle("unwatch", |lifecycle| {
+            lifecycle.watches.push((id.to_owned(), false))
+        })
+    })
+    .build()?;
+    m.function("link", |id: &str| {
+        update_lifecycle("link", |lifecycle| {
+            lifecycle.links.push((id.to_owned(), true))
+        })
+    })
+    .build()?;
+    m.function("unlink", |id: &str| {
+        update_lifecycle("unlink", |lifecycle| {
+            lifecycle.links.push((id.to_owned(), false))
+        })
+    })
+    .build()?;
+    m.function("trap_exit", |trap: bool| {
+        update_lifecycle("trap_exit", |lifecycle| lifecycle.trap_exit = Some(trap))
+    })
+    .build()?;
+    m.function("exit", || {
+        update_lifecycle("exit", |lifecycle| lifecycle.exit = true)
+    })
+    .build()?;
+    m.raw_function("spawn", |stack, addr, args, out| {
+        spawn(stack, addr, args, out, false, false)
+    })
+    .build()?;
+    m.raw_function("spawn_supervised", |stack, addr, args, out| {
+        spawn(stack, addr, args, out, true, false)
+    })
+    .build()?;
+    m.raw_function("spawn_file", |stack, addr, args, out| {
+        spawn(stack, addr, args, out, false, true)
+    })
+    .build()?;
+    m.raw_function("spawn_supervised_file", |stack, addr, args, out| {
+        spawn(stack, addr, args, out, true, true)
+    })
+    .build()?;
+    m.function("plot", |value: &str| teleplot(value)).build()?;
+    m.function(
+        "db_store",
+        |key: &str, value: rune::Value| -> VmResult<()> {
+            let value = vm_try!(db_value(serde_json::to_value(value)));
+            db_call(|db| db.store(key, value))
+        },
+    )
+    .build()?;
+    m.function("db_load", |key: &str| -> VmResult<Option<rune::Value>> {
+        let value = vm_try!(db_call(|db| db.load::<serde_json::Value>(key)));
+        match value {
+            Some(value) => VmResult::Ok(Some(vm_try!(db_value(serde_json::from_value(value))))),
+            None => VmResult::Ok(None),
+        }
+    })
+    .build()?;
+    m.function("db_delete", |key: &str| -> VmResult<()> {
+        db_call(|db| db.delete(key))
+    })
+    .build()?;
+    m.function("db_exists", |key: &str| -> VmResult<bool> {
+        db_call(|db| db.exists(key))
+    })
+    .build()?;
+    m.function(
+        "db_scan",
+        |prefix: &str| -> VmResult<Vec<(String, rune::Value)>> {
+            let entries = vm_try!(db_call(|db| db.scan_prefix::<serde_json::Value>(prefix)));
+            let mut values = Vec::with_capacity(entries.len());
+            for (key, value) in entries {
+                values.push((key, vm_try!(db_value(serde_json::from_value(value)))));
+            }
+            VmResult::Ok(values)
+        },
+    )
+    .build()?;
+    Ok(m)
+}
+
+/// Runs `f` on the db of the calling actor, `storage/actors/{id}`
+///
+/// Runs it on the copy in the scratch directory instead if one is set, see
+/// [`set_scratch_dbs`].
+fn db_call<T>(f: impl FnOnce(&Db) -> anyhow::Result<T>) -> VmResult<T> {
+    let Some(id) = with_binding(|binding| binding.id.clone()) else {
+        return VmResult::panic("db functions can only be called by an actor");
+    };
+    let result = actor_db(&id).and_then(|db| f(&db));
+    match result {
+        Ok(value) => VmResult::Ok(value),
+        Err(e) => VmResult::panic(format!("db of actor {id} failed: {e}")),
+    }
+}
+
+fn actor_db(id: &str) -> anyhow::Result<Db> {
+    let live = Path::new(STORAGE).join(ACTOR_DBS);
+    let Some(scratch) = SCRATCH_DBS.with_borrow(Clone::clone) else {
+        return Ok(Db::new(live, id));
+    };
+    let copy = scratch.join(id);
+    if !std::fs::exists(&copy)? && std::fs::exists(live.join(id))? {
+        std::fs::create_dir_all(&scratch)?;
+        std::fs::copy(live.join(id), &copy)?;
+    }
+    Ok(Db::new(scratch, id))
+}
+
+/// Turns a failed conversion between JSON and Rune values into a script panic
+fn db_value<T>(value: serde_json::Result<T>) -> VmResult<T> {
+    match value {
+        Ok(value) => VmResult::Ok(value),
+        Err(e) => VmResult::panic(format!("invalid db value: {e}")),
+    }
+}
+
+/// `ask(to, payload, timeout_ticks)`: sends a request and returns its correlation id
+///
+/// The reply is handled by `handle_reply(state, id, reply)`. If none arrives within
+/// `timeout_ticks` ticks, `handle_timeout(state, id)` runs instead.
+fn ask(to: &str, value: rune::Value, timeout_ticks: u64) -> VmResult<String> {
+    let payload = match serde_json::to_value(value) {
+        Ok(payload) => payload,
+        Err(e) => return VmResult::panic(format!("invalid ask payload: {e}")),
+    };
+    let id = generate_id();
+    let asked = with_binding(|binding| {
+        binding.effects.outbox.push(Message {
+            from: Some(binding.id.clone()),
+            to: to.to_owned(),
+            payload,
+            correlation_id: Some(id.clone()),
+            ..Default::default()
+        });
+        binding.effects.asks.push((id.clone(), timeout_ticks));
+    });
+    if asked.is_none() {
+        return VmResult::panic("ask called outside of an actor");
+    }
+    VmResult::Ok(id)
+}
+
+/// `send_after(to, payload, delay)` and `schedule_every(to, payload, interval)`
+///
+/// Starts a timer of the calling actor and returns its id. The delay is a number of
+/// ticks or a string like `"500ms"` or `"2s"`.
+fn start_timer(to: &str, value: rune::Value, delay: rune::Value, repeat: bool) -> VmResult<String> {
+    let payload = match serde_json::to_value(value) {
+        Ok(payload) => payload,
+        Err(e) => return VmResult::panic(format!("invalid timer payload: {e}")),
+    };
+    let delay = match Delay::parse(&delay) {
+        Ok(delay) => delay,
+        Err(e) => return VmResult::panic(e),
+    };
+    let id = generate_id();
+    let started = with_binding(|binding| {
+        binding.effects.timers.push(Timer {
+            id: id.clone(),
+            message: Message {
+                from: Some(binding.id.clone()),
+                to: to.to_owned(),
+                payload,
+                ..Default::default()
+            },
+            delay,
+            repeat,
+        })
+    });
+    if started.is_none() {
+        return VmResult::panic("timers can only be started by an actor");
+    }
+    VmResult::Ok(id)
+}
+
+/// `publish(topic, payload)`: sends a message to every actor subscribed to the topic
+///
+/// The message has no receiver yet, the system looks up the subscribers when it
+/// delivers the message at the end of the tick.
+fn publish(topic: &str, value: rune::Value) -> VmResult<()> {
+    if let Err(e) = topics::check_topic(topic) {
+        return VmResult::panic(e);
+    }
+    let payload = match serde_json::to_value(value) {
+        Ok(payload) => payload,
+        Err(e) => return VmResult::panic(format!("invalid message payload: {e}")),
+    };
+    let published = with_binding(|binding| {
+        binding.effects.outbox.push(Message {
+            from: Some(binding.id.clone()),
+            payload,
+            topic: Some(topic.to_owned()),
+            ..Default::default()
+        })
+    });
+    if published.is_none() {
+        return VmResult::panic("publish called outside of an actor");
+    }
+    VmResult::Ok(())
+}
+
+/// `subscribe(pattern)` and `unsubscribe(pattern)` for the calling actor
+fn update_subscription(pattern: &str, subscribe: bool) -> VmResult<()> {
+    if let Err(e) = topics::check_pattern(pattern) {
+        return VmResult::panic(e);
+    }
+    let updated = with_binding(|binding| {
+        binding
+            .effects
+            .subscriptions
+            .push((pattern.to_owned(), subscribe))
+    });
+    if updated.is_none() {
+        return VmResult::panic("only actors can subscribe to topics");
+    }
+    VmResult::Ok(())
+}
+
+/// Records a call of the lifecycle host function `name` by the calling actor
+fn update_lifecycle(name: &str, f: impl FnOnce(&mut Lifecycle)) -> VmResult<()> {
+    if with_binding(|binding| f(&mut binding.effects.lifecycle)).is_none() {
+        return VmResult::panic(format!("{name} called outside of an actor"));
+    }
+    VmResult::Ok(())
+}
+
+/// `spawn(source, id?)`: queues a child of the calling actor and returns its id
+///
+/// With `supervised` set, the calling actor also becomes the child's supervisor. With
+/// `file` set, the first argument names a library script, see [`find_script`], which
+/// the child runs from its file.
+fn spawn(
+    stack: &mut dyn Memory,
+    addr: InstAddress,
+    args: usize,
+    out: Output,
+    supervised: bool,
+    file: bool,
+) -> VmResult<()> {
+    let (script, id) = match vm_try!(stack.slice_at(addr, args)) {
+        [script] => (vm_try!(from_value::<String>(script)), None),
+        [script, id] => (
+            vm_try!(from_value::<String>(script)),
+            match from_value::<String>(id) {
+                Ok(id) => Some(id),
+                Err(_) => vm_try!(from_value::<Option<String>>(id)),
+            },
+        ),
+        _ => {
+            return VmResult::panic(format!("spawn expects 1 or 2 arguments, but got {args}"));
+        }
+    };
+    let (script, file) = if file {
+        (String::new(), Some(script))
+    } else {
+        (script, None)
+    };
+    let id = id.unwrap_or_else(generate_id);
+    let queued = with_binding(|binding| {
+        binding.effects.spawns.push(Props {
+            script,
+            file,
+            id: Some(id.clone()),
+            parent: Some(binding.id.clone()),
+            supervisor: supervised.then(|| binding.id.clone()),
+            ..Default::default()
+        })
+    });
+    if queued.is_none() {
+        return VmResult::panic("spawn called outside of an actor");
+    }
+    vm_try!(out.store(stack, || rune::to_value(id)));
+    VmResult::Ok(())
+}
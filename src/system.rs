@@ -1,34 +1,49 @@
 #![allow(unused)]
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     env::VarError,
     io::Write,
     path::{Path, PathBuf},
     sync::Arc,
+    time::SystemTime,
 };
 
-use crate::common::{Message, Props, SYSTEM, teleplot};
+use crate::common::{
+    Db, DeadLetter, DeadLetterReason, Diagnostic, EVENT_BUFFER, ErrorPolicy, GroupRequest,
+    MAX_DEAD_LETTERS, MAX_HISTORY, Message, MessageKind, Props, RestartStrategy, SNAPSHOT_DB,
+    SupervisorSpec, TerminationReason, TickPolicy,
+    dirs::{ACTOR_DBS, STORAGE},
+    format_diagnostics,
+};
+use crate::groups::Group;
+use crate::journal::{Journal, JournalEvent};
+use crate::scripts::{self, Effects, Lifecycle, ScriptCache, ScriptRef};
+use crate::timers::{Scheduled, Timer, TimerWheel};
+use crate::topics::Topics;
 use bytes::Bytes;
 use lazy_static::lazy_static;
 use nanoid::nanoid;
 use rune::{
-    BuildError, Context, ContextError, Diagnostics, Module, Source, Sources, ToValue, Value, Vm,
+    BuildError, ContextError, ToValue, Value,
     diagnostics::EmitError,
     from_value,
     runtime::{Object, RuntimeError, VmError},
     source::FromPathError,
-    termcolor::{ColorChoice, StandardStream},
     to_value,
 };
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::sync::RwLock;
 use thiserror::Error;
+use tokio::sync::{broadcast, oneshot};
 
 #[derive(Debug, Error)]
 pub enum EosError {
     #[error("Actor with ID '{0}' already exists")]
     IdAlreadyExists(String),
+    #[error("Invalid ID {0:?}, IDs can't be empty, contain '/', '..' or NUL, or be \"$client\"")]
+    InvalidId(String),
     #[error("Rune allocation error {0}")]
     RuneAlloc(#[from] rune::alloc::Error),
     #[error("Rune VM error {0}")]
@@ -49,74 +64,414 @@ pub enum EosError {
     ShellError(#[from] shellexpand::LookupError<VarError>),
     #[error("IO error {0}")]
     Io(#[from] std::io::Error),
+    #[error("No history for tick {0}")]
+    UnknownTick(u64),
+    #[error("Actor '{0}' not found")]
+    UnknownActor(String),
+    #[error("Group '{0}' not found")]
+    UnknownGroup(String),
+    #[error("Script '{0}' not found in the script path")]
+    UnknownScript(String),
+    #[error("Failed to compile script\n{}", format_diagnostics(.0))]
+    Compile(Vec<Diagnostic>),
 }
 
 pub type EosResult<T> = Result<T, EosError>;
 
+/// Checks that `id` can name an actor or group
+///
+/// Ids end up in paths, like the one of the actor's db, so they must not be able to
+/// point anywhere else. [`ASK_CLIENT`] is taken by the clients of `eos ask`.
+pub fn check_id(id: &str) -> EosResult<()> {
+    if id.is_empty()
+        || id.contains('/')
+        || id.contains("..")
+        || id.contains('\0')
+        || id == ASK_CLIENT
+    {
+        return Err(EosError::InvalidId(id.to_owned()));
+    }
+    Ok(())
+}
+
+/// Where replies to requests made with `eos ask` go, no actor can have this id
+pub const ASK_CLIENT: &str = "$client";
+
 #[derive(ToValue)]
 pub struct InternalMessage {
     pub sender: Option<String>,
     pub payload: Value,
 }
 
-#[derive(Debug)]
+/// The last failure of an actor's `handle`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorError {
+    pub tick: u64,
+    pub error: String,
+    pub backtrace: String,
+    pub message: Message,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Actor {
     pub id: String,
     pub mailbox: VecDeque<Message>,
     pub send_queue: VecDeque<Message>,
+    /// the source of the script, empty if the actor runs the library script `file`
     pub script: String,
+    #[serde(default)]
+    pub file: Option<String>,
     pub state: JsonValue,
     pub paused: bool,
+    pub parent: Option<String>,
+    pub children: Vec<String>,
+    pub last_error: Option<ActorError>,
+    /// how often the message at the front of the mailbox failed in a row
+    pub failures: u32,
+    pub supervisor: Option<String>,
+    pub supervision: SupervisorSpec,
+    /// actors supervised by this one, in the order they were started
+    pub supervised: Vec<String>,
+    /// ticks in which this actor restarted one of its children, within the restart window
+    pub restarts: VecDeque<u64>,
+    /// requests made with `ask` that wait for a reply, by correlation id, with the tick
+    /// they time out in
+    #[serde(default)]
+    pub pending_asks: BTreeMap<String, u64>,
+    /// the actors that are told when this one terminates, see `watch`
+    #[serde(default)]
+    pub watchers: BTreeSet<String>,
+    /// the actors this one terminates together with, see `link`
+    #[serde(default)]
+    pub links: BTreeSet<String>,
+    /// whether this actor is told when a linked actor terminates instead of terminating too
+    #[serde(default)]
+    pub trap_exit: bool,
+    /// why the last attempt to replace the script failed, empty if it didn't
+    #[serde(skip)]
+    pub script_errors: String,
+    /// what the compiler found in the last script that didn't compile, empty if it did
+    #[serde(skip)]
+    pub script_diagnostics: Vec<Diagnostic>,
+    #[serde(skip)]
+    pub changes: ActorChanges,
+}
+
+/// When something shown in the filesystem last changed, and how often it did
+#[derive(Debug, Clone, Copy)]
+pub struct Modified {
+    pub time: SystemTime,
+    /// counts the changes, clients see it as the qid version
+    pub version: u32,
+}
+
+impl Default for Modified {
+    fn default() -> Self {
+        Modified {
+            time: SystemTime::now(),
+            version: 0,
+        }
+    }
+}
+
+impl Modified {
+    pub fn touch(&mut self) {
+        self.time = SystemTime::now();
+        self.version = self.version.wrapping_add(1);
+    }
+}
+
+/// When the parts of an actor that show up as files last changed
+#[derive(Debug, Clone, Default)]
+pub struct ActorChanges {
+    pub spawned: Modified,
+    pub state: Modified,
+    pub mailbox: Modified,
+    pub script: Modified,
+    pub paused: Modified,
+    pub error: Modified,
+    pub script_errors: Modified,
+    pub diagnostics: Modified,
+    pub timers: Modified,
+}
+
+impl ActorChanges {
+    fn touch_all(&mut self) {
+        for modified in [
+            &mut self.state,
+            &mut self.mailbox,
+            &mut self.script,
+            &mut self.paused,
+            &mut self.error,
+            &mut self.script_errors,
+            &mut self.diagnostics,
+            &mut self.timers,
+        ] {
+            modified.touch();
+        }
+    }
+}
+
+/// When the parts of the system that show up as files last changed
+#[derive(Debug, Clone, Default)]
+pub struct SystemChanges {
+    pub started: Modified,
+    /// actors were spawned or killed
+    pub actors: Modified,
+    pub spawn_queue: Modified,
+    pub dead_letters: Modified,
+    pub history: Modified,
+    pub topics: Modified,
+    pub groups: Modified,
 }
 
 impl Actor {
-    pub async fn new(id: &str, script: &str) -> EosResult<Self> {
-        let state = init(id, &script).await?;
-        Ok(Actor {
+    /// Creates the actor by running the script's `init`, returning the effects `init` requested
+    pub async fn new(
+        id: &str,
+        script: ScriptRef<'_>,
+        scripts: &mut ScriptCache,
+    ) -> EosResult<(Self, Effects)> {
+        let (state, effects) = init(id, script, scripts).await?;
+        let (script, file) = match script {
+            ScriptRef::Inline(source) => (source.to_owned(), None),
+            ScriptRef::File(name) => (String::new(), Some(name.to_owned())),
+        };
+        let actor = Actor {
             id: id.to_string(),
-            script: script.to_owned(),
+            script,
+            file,
             state: serde_json::to_value(state)?,
             mailbox: VecDeque::new(),
             send_queue: VecDeque::new(),
             paused: false,
-        })
+            parent: None,
+            children: Vec::new(),
+            last_error: None,
+            failures: 0,
+            supervisor: None,
+            supervision: SupervisorSpec::default(),
+            supervised: Vec::new(),
+            restarts: VecDeque::new(),
+            pending_asks: BTreeMap::new(),
+            watchers: BTreeSet::new(),
+            links: BTreeSet::new(),
+            trap_exit: false,
+            script_errors: String::new(),
+            script_diagnostics: Vec::new(),
+            changes: ActorChanges::default(),
+        };
+        Ok((actor, effects))
     }
 
-    pub async fn run(
-        &mut self,
-        spawn_queue: &mut Vec<Props>,
-        message: Message,
-    ) -> EosResult<Option<Message>> {
-        let mut vm = make_vm(&self.id, &self.script).await?;
+    /// The code this actor runs
+    pub fn script_ref(&self) -> ScriptRef<'_> {
+        match &self.file {
+            Some(name) => ScriptRef::File(name),
+            None => ScriptRef::Inline(&self.script),
+        }
+    }
+
+    /// Replaces the state, noting the change if it is a different one
+    pub fn set_state(&mut self, state: JsonValue) {
+        if self.state != state {
+            self.state = state;
+            self.changes.state.touch();
+        }
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused != paused {
+            self.paused = paused;
+            self.changes.paused.touch();
+        }
+    }
+
+    /// Resets the state by running the script's `init` again
+    ///
+    /// The mailbox is kept, so the actor carries on with the next message. Returns the
+    /// effects `init` requested.
+    pub async fn restart(&mut self, scripts: &mut ScriptCache) -> EosResult<Effects> {
+        let (state, effects) = init(&self.id, self.script_ref(), scripts).await?;
+        self.set_state(serde_json::to_value(state)?);
+        self.failures = 0;
+        self.set_paused(false);
+        Ok(effects)
+    }
+
+    /// Waits for the replies to the requests `asks` made in `tick`
+    fn expect_replies(&mut self, asks: Vec<(String, u64)>, tick: u64) {
+        self.pending_asks
+            .extend(asks.into_iter().map(|(id, ticks)| (id, tick + ticks)));
+    }
+
+    /// Handles `message` and returns the effects the script requested
+    ///
+    /// Depending on the kind of the message, `handle`, `handle_reply` or `handle_timeout`
+    /// runs. A response to a message passed to `handle` is appended to the returned
+    /// outbox, as a reply if the message was a request made with `ask`.
+    pub async fn run(&mut self, scripts: &mut ScriptCache, message: Message) -> EosResult<Effects> {
+        let mut vm = scripts.vm(self.script_ref())?;
         log::info!("{message:?}");
-        let result = vm.call(
-            ["handle"],
-            (
-                serde_json::from_value::<rune::Value>(self.state.clone())?,
-                serde_json::from_value::<rune::Value>(serde_json::to_value(&message.payload)?)?,
-            ),
-        )?;
+        let state = serde_json::from_value::<rune::Value>(self.state.clone())?;
+        let payload = serde_json::from_value::<rune::Value>(message.payload.clone())?;
+        let id = message.correlation_id.clone().unwrap_or_default();
+        let (result, mut effects) = match message.kind {
+            MessageKind::Reply if vm.lookup_function(["handle_reply"]).is_ok() => {
+                scripts::bind(&self.id, || vm.call(["handle_reply"], (state, id, payload)))
+            }
+            MessageKind::Timeout => {
+                if vm.lookup_function(["handle_timeout"]).is_err() {
+                    return Ok(Effects::default());
+                }
+                scripts::bind(&self.id, || vm.call(["handle_timeout"], (state, id)))
+            }
+            MessageKind::Terminated if vm.lookup_function(["handle_terminated"]).is_ok() => {
+                let id = message.from.clone().unwrap_or_default();
+                let reason =
+                    serde_json::from_value::<rune::Value>(message.payload["reason"].clone())?;
+                scripts::bind(&self.id, || {
+                    vm.call(["handle_terminated"], (state, id, reason))
+                })
+            }
+            // without `handle_reply` or `handle_terminated`, `handle` gets these messages too
+            _ => scripts::bind(&self.id, || vm.call(["handle"], (state, payload))),
+        };
+        let result = result?;
         if let Ok((state, response)) = from_value::<(Object, Object)>(&result) {
-            self.state = serde_json::to_value(rune::Value::new(state)?)?;
-            if let Some(from) = message.from {
-                return Ok(Some(Message {
-                    from: message.to.into(),
+            self.set_state(serde_json::to_value(rune::Value::new(state)?)?);
+            if message.kind == MessageKind::Tell
+                && let Some(to) = message.reply_address()
+            {
+                effects.outbox.push(Message {
+                    from: Some(message.to.clone()),
+                    to: to.to_owned(),
                     payload: serde_json::to_value(rune::Value::new(response)?)?,
-                    to: from,
-                }));
+                    correlation_id: message.correlation_id.clone(),
+                    kind: if message.correlation_id.is_some() {
+                        MessageKind::Reply
+                    } else {
+                        MessageKind::Tell
+                    },
+                    ..Default::default()
+                });
             }
         } else if let Ok(state) = from_value::<Object>(&result) {
-            self.state = serde_json::to_value(rune::Value::new(state)?)?;
+            self.set_state(serde_json::to_value(rune::Value::new(state)?)?);
+        }
+        Ok(effects)
+    }
+}
+
+/// The system as it was at the end of a tick
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub tick: u64,
+    /// when the tick ended
+    pub time: SystemTime,
+    pub system: JsonValue,
+}
+
+impl Checkpoint {
+    /// The actors of the checkpointed system, keyed by id
+    pub fn actors(&self) -> Option<&serde_json::Map<String, JsonValue>> {
+        self.system.get("actors")?.as_object()
+    }
+}
+
+/// Something that happened in the system, as streamed to watchers
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// the tick the event happened in
+    pub tick: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventKind {
+    /// a message was put into the mailbox of an actor
+    Delivered {
+        message: Message,
+    },
+    StateChanged {
+        actor: String,
+        state: JsonValue,
+    },
+    Spawned {
+        actor: String,
+    },
+    Killed {
+        actor: String,
+    },
+    /// an actor's `handle` failed
+    Failed {
+        actor: String,
+        error: String,
+    },
+    /// the tick ended
+    Tick,
+}
+
+impl Event {
+    /// The actor the event is about, `None` for events about the whole system
+    pub fn actor(&self) -> Option<&str> {
+        match &self.kind {
+            EventKind::Delivered { message } => Some(&message.to),
+            EventKind::StateChanged { actor, .. }
+            | EventKind::Spawned { actor }
+            | EventKind::Killed { actor }
+            | EventKind::Failed { actor, .. } => Some(actor),
+            EventKind::Tick => None,
         }
-        Ok(None)
     }
 }
 
-#[derive(Debug)]
+fn event_channel() -> broadcast::Sender<Event> {
+    broadcast::channel(EVENT_BUFFER).0
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct System {
     pub spawn_queue: Vec<Props>,
     pub actors: HashMap<String, Actor>,
     pub paused: bool,
+    pub policy: TickPolicy,
+    pub error_policy: ErrorPolicy,
+    /// compiled scripts, rebuilt on demand after a restore
+    #[serde(skip)]
+    pub scripts: ScriptCache,
+    /// number of ticks the system has run
+    pub current_tick: u64,
+    pub dead_letters: VecDeque<DeadLetter>,
+    /// messages actors scheduled with `send_after` and `schedule_every`
+    #[serde(default)]
+    pub timers: TimerWheel,
+    /// the topics actors subscribed to with `subscribe`
+    #[serde(default)]
+    pub topics: Topics,
+    /// groups of actors addressed by a single id, by id
+    #[serde(default)]
+    pub groups: BTreeMap<String, Group>,
+    /// actors that called `exit`, they terminate at the end of the tick
+    #[serde(default)]
+    pub exits: Vec<String>,
+    /// where changes are recorded while `eos serve --journal` runs
+    #[serde(skip)]
+    pub journal: Option<Journal>,
+    /// the last [`MAX_HISTORY`] ticks, oldest first
+    #[serde(skip)]
+    pub history: VecDeque<Checkpoint>,
+    /// where [`Event`]s are sent, subscribe to watch the system
+    #[serde(skip, default = "event_channel")]
+    pub events: broadcast::Sender<Event>,
+    /// requests made with `eos ask` that wait for a reply, by correlation id, with the
+    /// tick they time out in
+    #[serde(skip)]
+    pub client_asks: HashMap<String, (u64, oneshot::Sender<JsonValue>)>,
+    #[serde(skip)]
+    pub changes: SystemChanges,
 }
 
 impl System {
@@ -125,125 +480,1293 @@ impl System {
             spawn_queue: Vec::new(),
             actors: HashMap::new(),
             paused: false,
+            policy: TickPolicy::default(),
+            error_policy: ErrorPolicy::default(),
+            scripts: ScriptCache::default(),
+            current_tick: 0,
+            dead_letters: VecDeque::new(),
+            timers: TimerWheel::default(),
+            topics: Topics::default(),
+            groups: BTreeMap::new(),
+            exits: Vec::new(),
+            journal: None,
+            history: VecDeque::new(),
+            events: event_channel(),
+            client_asks: HashMap::new(),
+            changes: SystemChanges::default(),
+        }
+    }
+
+    /// Remembers the system as it is now as the checkpoint of the current tick
+    pub fn checkpoint(&mut self) -> EosResult<()> {
+        let checkpoint = Checkpoint {
+            tick: self.current_tick,
+            time: SystemTime::now(),
+            system: serde_json::to_value(&*self)?,
+        };
+        if self
+            .history
+            .back()
+            .is_some_and(|last| last.tick >= checkpoint.tick)
+        {
+            self.history.retain(|past| past.tick < checkpoint.tick);
         }
+        if self.history.len() >= MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(checkpoint);
+        self.changes.history.touch();
+        Ok(())
+    }
+
+    pub fn checkpoint_at(&self, tick: u64) -> Option<&Checkpoint> {
+        self.history
+            .iter()
+            .find(|checkpoint| checkpoint.tick == tick)
+    }
+
+    /// Turns the system back into the checkpoint of `tick`, forgetting every later tick
+    pub fn restore_checkpoint(&mut self, tick: u64) -> EosResult<()> {
+        let checkpoint = self
+            .checkpoint_at(tick)
+            .ok_or(EosError::UnknownTick(tick))?
+            .system
+            .clone();
+        let mut restored: System = serde_json::from_value(checkpoint.clone())?;
+        restored.scripts = std::mem::take(&mut self.scripts);
+        restored.journal = self.journal.take();
+        restored.history = std::mem::take(&mut self.history);
+        restored.history.retain(|past| past.tick <= tick);
+        restored.events = self.events.clone();
+        restored.client_asks = std::mem::take(&mut self.client_asks);
+        // everything may have changed, but versions must not go back
+        restored.changes = std::mem::take(&mut self.changes);
+        restored.changes.actors.touch();
+        restored.changes.spawn_queue.touch();
+        restored.changes.dead_letters.touch();
+        for (id, actor) in &mut restored.actors {
+            if let Some(old) = self.actors.get(id) {
+                actor.changes = old.changes.clone();
+            }
+            actor.changes.touch_all();
+        }
+        *self = restored;
+        self.record(|| JournalEvent::Restored { system: checkpoint });
+        self.prune_scripts();
+        log::info!("restored tick {tick}");
+        Ok(())
     }
+
+    /// Steps the system back by `ticks` ticks and returns the tick it is at now
+    pub fn rewind(&mut self, ticks: u64) -> EosResult<u64> {
+        let tick = self.current_tick.saturating_sub(ticks);
+        self.restore_checkpoint(tick)?;
+        Ok(tick)
+    }
+
+    /// Sends the event built by `event` to the watchers of the system, if there are any
+    pub fn emit(&self, event: impl FnOnce() -> EventKind) {
+        if self.events.receiver_count() > 0 {
+            // fails only if the last watcher left in the meantime
+            _ = self.events.send(Event {
+                tick: self.current_tick,
+                kind: event(),
+            });
+        }
+    }
+
+    /// Records the event built by `event` if a journal is running
+    pub fn record(&mut self, event: impl FnOnce() -> JournalEvent) {
+        if let Some(journal) = &mut self.journal {
+            journal.record(event());
+        }
+    }
+
+    /// Stores a snapshot of the whole system under `name` in the snapshot db
+    pub fn save_snapshot(&self, name: &str) -> anyhow::Result<()> {
+        Db::new(STORAGE, SNAPSHOT_DB).store(name, serde_json::to_value(self)?)?;
+        log::info!("saved snapshot {name:?} at tick {}", self.current_tick);
+        Ok(())
+    }
+
+    /// Loads the snapshot stored under `name` in the snapshot db
+    pub fn load_snapshot(name: &str) -> anyhow::Result<Option<Self>> {
+        if !std::fs::exists(Path::new(STORAGE).join(SNAPSHOT_DB))? {
+            return Ok(None);
+        }
+        let snapshot = Db::new(STORAGE, SNAPSHOT_DB).load::<JsonValue>(name)?;
+        Ok(snapshot.map(serde_json::from_value).transpose()?)
+    }
+
+    /// Puts `message` into the mailbox of its recipient, or into the dead letters if there is none
+    ///
+    /// A published message that has no recipient yet goes to the subscribers of its topic,
+    /// one sent to a group to the members its routing picks.
+    pub fn deliver(&mut self, message: Message) {
+        if message.to.is_empty() && message.topic.is_some() {
+            self.publish(message);
+            return;
+        }
+        if let Some(group) = self.groups.get_mut(&message.to) {
+            let members = group.route(&message.payload);
+            self.record(|| JournalEvent::Routed {
+                group: message.to.clone(),
+                payload: message.payload.clone(),
+            });
+            for to in members {
+                self.deliver(Message {
+                    to,
+                    ..message.clone()
+                });
+            }
+            return;
+        }
+        if message.kind == MessageKind::Reply {
+            let id = message.correlation_id.as_deref().unwrap_or_default();
+            if let Some((_, client)) = self.client_asks.remove(id) {
+                // the client may have given up waiting already
+                _ = client.send(message.payload);
+                return;
+            }
+            let expected = self
+                .actors
+                .get_mut(&message.to)
+                .is_some_and(|actor| actor.pending_asks.remove(id).is_some());
+            if !expected {
+                self.dead_letter(message, DeadLetterReason::UnexpectedReply);
+                return;
+            }
+        }
+        if let Some(actor) = self.actors.get_mut(&message.to) {
+            if let Some(journal) = &mut self.journal {
+                journal.record(JournalEvent::Delivered {
+                    message: message.clone(),
+                });
+            }
+            actor.mailbox.push_back(message.clone());
+            actor.changes.mailbox.touch();
+            self.emit(|| EventKind::Delivered { message });
+        } else {
+            self.dead_letter(message, DeadLetterReason::UnknownActor);
+        }
+    }
+
+    /// Sends `payload` to the actor or group `to` for `eos ask`, the reply arrives through
+    /// the receiver
+    ///
+    /// The receiver fails if no reply is delivered within `timeout_ticks` ticks. A group
+    /// routes the request like any other message, and only the first reply is passed on
+    /// if it broadcasts.
+    pub fn ask(
+        &mut self,
+        to: &str,
+        payload: JsonValue,
+        timeout_ticks: u64,
+    ) -> EosResult<oneshot::Receiver<JsonValue>> {
+        if !self.actors.contains_key(to) && !self.groups.contains_key(to) {
+            return Err(EosError::UnknownActor(to.to_owned()));
+        }
+        let id = nanoid!();
+        let (client, receiver) = oneshot::channel();
+        self.client_asks
+            .insert(id.clone(), (self.current_tick + timeout_ticks, client));
+        self.deliver(Message {
+            from: None,
+            to: to.to_owned(),
+            payload,
+            correlation_id: Some(id),
+            reply_to: Some(ASK_CLIENT.to_owned()),
+            ..Default::default()
+        });
+        Ok(receiver)
+    }
+
+    /// Gives up on every `ask` whose reply wasn't delivered in time
+    ///
+    /// Actors get a timeout message, clients of `eos ask` see their receiver fail.
+    fn expire_asks(&mut self) {
+        let tick = self.current_tick;
+        self.client_asks.retain(|_, (timeout, _)| *timeout > tick);
+        let mut timeouts = Vec::new();
+        for actor in self.actors.values_mut() {
+            actor.pending_asks.retain(|id, timeout| {
+                if *timeout > tick {
+                    return true;
+                }
+                timeouts.push(Message {
+                    from: None,
+                    to: actor.id.clone(),
+                    payload: JsonValue::Null,
+                    correlation_id: Some(id.clone()),
+                    kind: MessageKind::Timeout,
+                    ..Default::default()
+                });
+                false
+            });
+        }
+        timeouts.sort_by(|a, b| (&a.to, &a.correlation_id).cmp(&(&b.to, &b.correlation_id)));
+        for message in timeouts {
+            self.deliver(message);
+        }
+    }
+
+    /// Applies the timers the actor `owner` started and cancelled while it ran
+    ///
+    /// Actors can only cancel their own timers.
+    fn update_timers(&mut self, owner: &str, started: Vec<Timer>, cancelled: Vec<String>) {
+        if started.is_empty() && cancelled.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        for timer in started {
+            let Some(due) = self.timers.schedule(timer.clone(), self.current_tick, now) else {
+                log::warn!(
+                    "timer {} of {owner} is due too far off, dropping it",
+                    timer.id
+                );
+                continue;
+            };
+            self.record(|| JournalEvent::TimerScheduled {
+                scheduled: Scheduled { timer, due },
+            });
+        }
+        for id in cancelled {
+            if self
+                .timers
+                .get(&id)
+                .is_some_and(|scheduled| scheduled.timer.owner() == owner)
+            {
+                self.timers.cancel(&id);
+                self.record(|| JournalEvent::TimerCancelled { id });
+            }
+        }
+        if let Some(actor) = self.actors.get_mut(owner) {
+            actor.changes.timers.touch();
+        }
+    }
+
+    /// Applies the `subscribe` and `unsubscribe` calls the actor `owner` made while it ran
+    fn update_subscriptions(&mut self, owner: &str, subscriptions: Vec<(String, bool)>) {
+        for (pattern, subscribe) in subscriptions {
+            let changed = if subscribe {
+                self.topics.subscribe(&pattern, owner)
+            } else {
+                self.topics.unsubscribe(&pattern, owner)
+            };
+            if !changed {
+                continue;
+            }
+            self.changes.topics.touch();
+            let actor = owner.to_owned();
+            self.record(|| {
+                if subscribe {
+                    JournalEvent::Subscribed { actor, pattern }
+                } else {
+                    JournalEvent::Unsubscribed { actor, pattern }
+                }
+            });
+        }
+    }
+
+    /// Applies the `watch`, `link`, `trap_exit` and `exit` calls `owner` made while it ran
+    ///
+    /// Watching or linking an actor that doesn't exist tells `owner` right away that it
+    /// terminated. An actor that called `exit` terminates at the end of the tick, after
+    /// the messages it sent are delivered.
+    fn update_lifecycle(
+        &mut self,
+        owner: &str,
+        Lifecycle {
+            watches,
+            links,
+            trap_exit,
+            exit,
+        }: Lifecycle,
+    ) {
+        if let Some(trap) = trap_exit
+            && let Some(actor) = self.actors.get_mut(owner)
+        {
+            actor.trap_exit = trap;
+            self.record(|| JournalEvent::TrapExit {
+                actor: owner.to_owned(),
+                trap,
+            });
+        }
+        for (target, watch) in watches {
+            match self.actors.get_mut(&target) {
+                Some(actor) => {
+                    if watch {
+                        actor.watchers.insert(owner.to_owned());
+                    } else {
+                        actor.watchers.remove(owner);
+                    }
+                    self.record(|| JournalEvent::Watched {
+                        actor: target,
+                        watcher: owner.to_owned(),
+                        watch,
+                    });
+                }
+                None if watch => {
+                    self.notify_terminated(owner, &target, &TerminationReason::UnknownActor)
+                }
+                None => {}
+            }
+        }
+        for (target, link) in links {
+            if target == owner {
+                continue;
+            }
+            if !self.actors.contains_key(&target) {
+                if link {
+                    self.notify_terminated(owner, &target, &TerminationReason::UnknownActor);
+                }
+                continue;
+            }
+            self.set_link(owner, &target, link);
+            self.record(|| JournalEvent::Linked {
+                actor: owner.to_owned(),
+                other: target,
+                link,
+            });
+        }
+        if exit {
+            self.exits.push(owner.to_owned());
+        }
+    }
+
+    /// Links or unlinks the actors `a` and `b`, in both directions
+    pub fn set_link(&mut self, a: &str, b: &str, link: bool) {
+        for (actor, other) in [(a, b), (b, a)] {
+            if let Some(actor) = self.actors.get_mut(actor) {
+                if link {
+                    actor.links.insert(other.to_owned());
+                } else {
+                    actor.links.remove(other);
+                }
+            }
+        }
+    }
+
+    /// Tells the actor `to` that the actor `id` terminated
+    fn notify_terminated(&mut self, to: &str, id: &str, reason: &TerminationReason) {
+        self.deliver(Message {
+            from: Some(id.to_owned()),
+            to: to.to_owned(),
+            payload: serde_json::json!({
+                "event": "terminated",
+                "id": id,
+                "reason": reason,
+            }),
+            kind: MessageKind::Terminated,
+            ..Default::default()
+        });
+    }
+
+    /// Delivers a copy of `message` to every actor subscribed to its topic
+    ///
+    /// Returns the actors it was delivered to. A topic nobody is subscribed to is not an
+    /// error, the message is dropped without becoming a dead letter.
+    pub fn publish(&mut self, message: Message) -> Vec<String> {
+        let topic = message.topic.as_deref().unwrap_or_default();
+        let subscribers: Vec<String> = self
+            .topics
+            .subscribers(topic)
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        for to in &subscribers {
+            self.deliver(Message {
+                to: to.clone(),
+                ..message.clone()
+            });
+        }
+        subscribers
+    }
+
+    /// Delivers the messages of the timers that are due at the end of this tick
+    fn fire_timers(&mut self) {
+        for timer in self.timers.take_due(self.current_tick, SystemTime::now()) {
+            if let Some(owner) = self.actors.get_mut(timer.owner()) {
+                owner.changes.timers.touch();
+            }
+            // a repeating timer is back in the wheel already
+            let next = self.timers.get(&timer.id).map(|scheduled| scheduled.due);
+            self.record(|| JournalEvent::TimerFired {
+                id: timer.id.clone(),
+                next,
+            });
+            self.deliver(timer.message);
+        }
+    }
+
+    pub fn dead_letter(&mut self, message: Message, reason: DeadLetterReason) {
+        log::warn!("dead letter: {reason:?} {message:?}");
+        self.record(|| JournalEvent::DeadLettered {
+            message: message.clone(),
+            reason: reason.clone(),
+        });
+        if self.dead_letters.len() >= MAX_DEAD_LETTERS {
+            self.dead_letters.pop_front();
+        }
+        self.changes.dead_letters.touch();
+        self.dead_letters.push_back(DeadLetter {
+            message,
+            reason,
+            tick: self.current_tick,
+        });
+    }
+
+    /// Delivers all dead letters again and returns how many there were
+    ///
+    /// Letters that still can't be delivered end up in the dead letters again.
+    pub fn replay_dead_letters(&mut self) -> usize {
+        let letters = std::mem::take(&mut self.dead_letters);
+        let count = letters.len();
+        self.changes.dead_letters.touch();
+        for DeadLetter { message, .. } in letters {
+            self.deliver(message);
+        }
+        count
+    }
+
+    /// Drops compiled scripts that no actor is running anymore
+    pub fn prune_scripts(&mut self) {
+        let in_use: HashSet<ScriptRef> = self.actors.values().map(Actor::script_ref).collect();
+        self.scripts.retain(&in_use);
+    }
+    /// Kills the actor `id` together with every actor it supervises
     pub async fn kill_actor(&mut self, id: &str) -> EosResult<()> {
-        if let Some(_) = self.actors.remove(id) {
-            log::info!("killed: id:{id:?}");
+        self.terminate(id, TerminationReason::Killed).await
+    }
+
+    /// Removes the actor `id` for `reason`, together with every actor it supervises
+    ///
+    /// Its watchers and the linked actors that trap exits are told why it terminated.
+    /// Linked actors that don't trap exits terminate as well, unless it exited by itself.
+    async fn terminate(&mut self, id: &str, reason: TerminationReason) -> EosResult<()> {
+        let mut pending = vec![(id.to_owned(), reason)];
+        while let Some((id, reason)) = pending.pop() {
+            let Some(actor) = self.remove_actor(&id) else {
+                continue;
+            };
+            for message in actor.mailbox {
+                self.dead_letter(message, DeadLetterReason::ActorKilled);
+            }
+            pending.extend(
+                actor
+                    .supervised
+                    .into_iter()
+                    .map(|child| (child, TerminationReason::Killed)),
+            );
+            let mut notified = actor.watchers;
+            for linked in actor.links {
+                let Some(other) = self.actors.get(&linked) else {
+                    continue;
+                };
+                if other.trap_exit {
+                    notified.insert(linked);
+                } else if reason != TerminationReason::Exited {
+                    let reason = TerminationReason::Linked { actor: id.clone() };
+                    pending.push((linked, reason));
+                }
+            }
+            for watcher in notified {
+                if self.actors.contains_key(&watcher) {
+                    self.notify_terminated(&watcher, &id, &reason);
+                }
+            }
+            log::info!("terminated: id:{id:?} reason:{reason:?}");
+        }
+        self.prune_scripts();
+        Ok(())
+    }
+
+    /// Replaces the script of the actor `id`, migrating its state
+    ///
+    /// The actor runs `script` as inline source from then on, even if it ran a library
+    /// script before. The new script has to compile. If it has a `migrate(state)` function, it gets
+    /// the current state and returns the state the new script continues with. If either
+    /// fails, the actor keeps its script and state, and the error ends up in its
+    /// `script_errors`.
+    pub async fn reload_actor(&mut self, id: &str, script: &str) -> EosResult<()> {
+        let Some(actor) = self.actors.get_mut(id) else {
+            return Err(EosError::UnknownActor(id.to_owned()));
+        };
+        let migrated = migrate(id, script, &actor.state, &mut self.scripts).await;
+        // the journal records the children, not the ids `spawn` generated for them
+        scripts::take_generated_ids();
+        let (state, effects) = match migrated {
+            Ok(migrated) => migrated,
+            Err(e) => {
+                log::error!("Failed to reload actor {id}: {e}");
+                actor.script_errors = e.to_string();
+                actor.changes.script_errors.touch();
+                actor.script_diagnostics = match &e {
+                    EosError::Compile(diagnostics) => diagnostics.clone(),
+                    _ => Vec::new(),
+                };
+                actor.changes.diagnostics.touch();
+                return Err(e);
+            }
+        };
+        actor.script = script.to_owned();
+        actor.file = None;
+        actor.changes.script.touch();
+        if !actor.script_errors.is_empty() {
+            actor.script_errors.clear();
+            actor.changes.script_errors.touch();
         }
+        actor.script_diagnostics.clear();
+        actor.changes.diagnostics.touch();
+        actor.set_state(state.clone());
+        self.apply_effects(id, effects);
+        self.record(|| JournalEvent::ScriptWritten {
+            actor: id.to_owned(),
+            script: script.to_owned(),
+        });
+        self.record(|| JournalEvent::StateWritten {
+            actor: id.to_owned(),
+            state: state.clone(),
+        });
+        self.emit(|| EventKind::StateChanged {
+            actor: id.to_owned(),
+            state,
+        });
+        // the old unit is stale unless another actor still runs it
+        self.prune_scripts();
+        log::info!("reloaded actor {id}");
         Ok(())
     }
 
-    pub async fn spawn_actor(&mut self, Props { script, id }: Props) -> EosResult<String> {
+    /// Reloads every actor that runs the same script as the actor `id`, see [`Self::reload_actor`]
+    ///
+    /// Returns the result of each reload, by actor id.
+    pub async fn reload_shared(
+        &mut self,
+        id: &str,
+        script: &str,
+    ) -> EosResult<Vec<(String, EosResult<()>)>> {
+        let old = self
+            .actors
+            .get(id)
+            .ok_or_else(|| EosError::UnknownActor(id.to_owned()))?
+            .script_ref();
+        let mut ids: Vec<String> = self
+            .actors
+            .values()
+            .filter(|actor| actor.script_ref() == old)
+            .map(|actor| actor.id.clone())
+            .collect();
+        ids.sort();
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let result = self.reload_actor(&id, script).await;
+            results.push((id, result));
+        }
+        Ok(results)
+    }
+
+    /// Removes the actor `id` and unlinks it from its parent and supervisor
+    ///
+    /// Its db is closed but kept.
+    pub fn remove_actor(&mut self, id: &str) -> Option<Actor> {
+        let actor = self.actors.remove(id)?;
+        Db::new(Path::new(STORAGE).join(ACTOR_DBS), id).close();
+        self.timers.cancel_all(id);
+        if self.topics.unsubscribe_all(id) {
+            self.changes.topics.touch();
+        }
+        for other in self.actors.values_mut() {
+            other.watchers.remove(id);
+            other.links.remove(id);
+        }
+        let mut left_group = false;
+        self.groups.retain(|_, group| {
+            left_group |= group.remove_member(id);
+            !group.members.is_empty()
+        });
+        if left_group {
+            self.changes.groups.touch();
+        }
+        self.changes.actors.touch();
+        self.record(|| JournalEvent::Killed {
+            actor: id.to_owned(),
+        });
+        self.emit(|| EventKind::Killed {
+            actor: id.to_owned(),
+        });
+        if let Some(parent) = actor
+            .parent
+            .as_ref()
+            .and_then(|parent| self.actors.get_mut(parent))
+        {
+            parent.children.retain(|child| child != id);
+        }
+        if let Some(supervisor) = actor
+            .supervisor
+            .as_ref()
+            .and_then(|supervisor| self.actors.get_mut(supervisor))
+        {
+            supervisor.supervised.retain(|child| child != id);
+        }
+        Some(actor)
+    }
+
+    /// Spawns the actor `props` describes and applies the effects its `init` requested
+    pub async fn spawn_actor(&mut self, props: Props) -> EosResult<String> {
+        let (id, effects) = self.create_actor(props).await?;
+        self.apply_effects(&id, effects);
+        Ok(id)
+    }
+
+    /// Applies the effects the actor `id` requested outside of a tick, in `init` or
+    /// `migrate`
+    ///
+    /// Its messages are sent and its spawns happen in the next tick.
+    fn apply_effects(&mut self, id: &str, effects: Effects) {
+        if let Some(actor) = self.actors.get_mut(id) {
+            actor.send_queue.extend(effects.outbox);
+            actor.expect_replies(effects.asks, self.current_tick);
+        }
+        if !effects.spawns.is_empty() {
+            self.spawn_queue.extend(effects.spawns);
+            self.changes.spawn_queue.touch();
+        }
+        self.update_timers(id, effects.timers, effects.cancelled_timers);
+        self.update_subscriptions(id, effects.subscriptions);
+        self.update_lifecycle(id, effects.lifecycle);
+    }
+
+    /// Creates the actor `props` describes, returning its id and the effects its `init`
+    /// requested without applying them
+    ///
+    /// A replayed journal creates actors this way, since whatever the effects caused is
+    /// recorded as events of its own.
+    pub async fn create_actor(
+        &mut self,
+        Props {
+            script,
+            file,
+            id,
+            parent,
+            supervisor,
+            supervision,
+        }: Props,
+    ) -> EosResult<(String, Effects)> {
         log::info!("spawn: id:{id:?}");
         let id = id.unwrap_or_else(|| nanoid!());
-        let actor = Actor::new(&id, &script).await?;
-        if self.actors.contains_key(&id) {
+        check_id(&id)?;
+        if self.actors.contains_key(&id) || self.groups.contains_key(&id) {
             return Err(EosError::IdAlreadyExists(id));
         }
+        let script_ref = match &file {
+            Some(name) => ScriptRef::File(name),
+            None => ScriptRef::Inline(&script),
+        };
+        let (mut actor, effects) = Actor::new(&id, script_ref, &mut self.scripts).await?;
+        let ids = scripts::take_generated_ids();
+        self.record(|| JournalEvent::Spawned {
+            props: Props {
+                script,
+                file,
+                id: Some(id.clone()),
+                parent: parent.clone(),
+                supervisor: supervisor.clone(),
+                supervision,
+            },
+            ids,
+        });
+        actor.supervision = supervision;
+        if let Some(parent) = parent.and_then(|parent| self.actors.get_mut(&parent)) {
+            parent.children.push(id.clone());
+            actor.parent = Some(parent.id.clone());
+        }
+        if let Some(supervisor) = supervisor.and_then(|supervisor| self.actors.get_mut(&supervisor))
+        {
+            supervisor.supervised.push(id.clone());
+            actor.supervisor = Some(supervisor.id.clone());
+        }
         self.actors.insert(id.clone(), actor);
-        Ok(id)
+        self.changes.actors.touch();
+        self.emit(|| EventKind::Spawned { actor: id.clone() });
+        Ok((id, effects))
+    }
+
+    /// Spawns the members of a new group and returns it, see [`crate::groups`]
+    ///
+    /// If one of the members fails to spawn, the ones spawned before it are killed again.
+    pub async fn create_group(
+        &mut self,
+        GroupRequest {
+            id,
+            script,
+            file,
+            size,
+            routing,
+            hash_key,
+        }: GroupRequest,
+    ) -> EosResult<Group> {
+        let id = id.unwrap_or_else(|| nanoid!());
+        check_id(&id)?;
+        if self.actors.contains_key(&id) || self.groups.contains_key(&id) {
+            return Err(EosError::IdAlreadyExists(id));
+        }
+        let mut group = Group::new(&id, routing, hash_key);
+        for n in 0..size as usize {
+            let props = Props {
+                script: script.clone(),
+                file: file.clone(),
+                id: Some(group.member_id(n)),
+                ..Default::default()
+            };
+            match self.spawn_actor(props).await {
+                Ok(member) => group.members.push(member),
+                Err(e) => {
+                    for member in &group.members {
+                        self.kill_actor(member).await?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        log::info!("group {id} created with {:?}", group.members);
+        self.record(|| JournalEvent::GroupCreated {
+            group: group.clone(),
+        });
+        self.groups.insert(id, group.clone());
+        self.changes.groups.touch();
+        Ok(group)
+    }
+
+    /// Kills the members of the group `id`, which removes the group
+    pub async fn delete_group(&mut self, id: &str) -> EosResult<()> {
+        let Some(group) = self.groups.get(id) else {
+            return Err(EosError::UnknownGroup(id.to_owned()));
+        };
+        for member in group.members.clone() {
+            self.kill_actor(&member).await?;
+        }
+        Ok(())
+    }
+
+    /// Spawns everything in the spawn queue in the order it was requested
+    ///
+    /// Failing spawns are logged and dropped, so one bad request can't block the queue.
+    /// An actor that requested a spawn that failed, e.g. because the id is taken, is told
+    /// with a `spawn_failed` message, since `spawn` already returned the id to it.
+    async fn spawn_queued(&mut self) {
+        let queue = std::mem::take(&mut self.spawn_queue);
+        if !queue.is_empty() {
+            self.changes.spawn_queue.touch();
+        }
+        for request in queue {
+            let (id, parent) = (request.id.clone(), request.parent.clone());
+            let Err(e) = self.spawn_actor(request).await else {
+                continue;
+            };
+            log::error!("Failed to spawn queued actor: {e}");
+            if let Some(parent) = parent {
+                self.deliver(Message {
+                    from: None,
+                    to: parent,
+                    payload: serde_json::json!({
+                        "event": "spawn_failed",
+                        "id": id,
+                        "reason": e.to_string(),
+                    }),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    /// Applies the error policy after `handle` of actor `id` failed on `message`
+    ///
+    /// Supervised actors are restarted by their supervisor instead of being paused.
+    async fn handle_failure(&mut self, id: &str, message: Message, error: EosError) {
+        let supervisor = self
+            .actors
+            .get(id)
+            .and_then(|actor| actor.supervisor.clone())
+            .filter(|supervisor| self.actors.contains_key(supervisor));
+        self.emit(|| EventKind::Failed {
+            actor: id.to_owned(),
+            error: error.to_string(),
+        });
+        let Some(actor) = self.actors.get_mut(id) else {
+            return;
+        };
+        log::error!("actor {id} failed to handle {message:?}: {error}");
+        let backtrace = match &error {
+            EosError::VmError(e) => self.scripts.backtrace(actor.script_ref(), e),
+            _ => String::new(),
+        };
+        let error = error.to_string();
+        actor.failures += 1;
+        actor.changes.error.touch();
+        actor.last_error = Some(ActorError {
+            tick: self.current_tick,
+            error: error.clone(),
+            backtrace,
+            message: message.clone(),
+        });
+        match (self.error_policy, supervisor) {
+            (ErrorPolicy::Retry { times }, _) if actor.failures <= times => {
+                actor.mailbox.push_front(message.clone());
+                actor.changes.mailbox.touch();
+                self.record(|| JournalEvent::Requeued {
+                    actor: id.to_owned(),
+                    message,
+                });
+            }
+            (ErrorPolicy::Pause, None) => {
+                actor.set_paused(true);
+                actor.mailbox.push_front(message.clone());
+                actor.changes.mailbox.touch();
+                self.record(|| JournalEvent::Requeued {
+                    actor: id.to_owned(),
+                    message,
+                });
+            }
+            (_, None) => {
+                actor.failures = 0;
+                self.dead_letter(message, DeadLetterReason::HandlerError { error });
+            }
+            (_, Some(supervisor)) => {
+                self.dead_letter(
+                    message,
+                    DeadLetterReason::HandlerError {
+                        error: error.clone(),
+                    },
+                );
+                self.supervise(&supervisor, id, &error).await;
+            }
+        }
+    }
+
+    /// Lets `supervisor` react to the failure of its child `failed`
+    ///
+    /// Restarts the children selected by the supervisor's strategy, or kills them if the
+    /// supervisor restarted too often within its window, and tells the supervisor about it.
+    async fn supervise(&mut self, supervisor: &str, failed: &str, error: &str) {
+        let current_tick = self.current_tick;
+        let Some(sup) = self.actors.get_mut(supervisor) else {
+            return;
+        };
+        let SupervisorSpec {
+            strategy,
+            max_restarts,
+            within_ticks,
+        } = sup.supervision;
+        let affected: Vec<String> = match strategy {
+            RestartStrategy::OneForOne => vec![failed.to_owned()],
+            RestartStrategy::OneForAll => sup.supervised.clone(),
+            RestartStrategy::RestForOne => sup
+                .supervised
+                .iter()
+                .skip_while(|child| *child != failed)
+                .cloned()
+                .collect(),
+        };
+        while sup
+            .restarts
+            .front()
+            .is_some_and(|tick| tick + within_ticks <= current_tick)
+        {
+            sup.restarts.pop_front();
+        }
+
+        let event = if sup.restarts.len() >= max_restarts as usize {
+            log::warn!("supervisor {supervisor} gave up on {affected:?}");
+            for child in &affected {
+                let reason = if child == failed {
+                    TerminationReason::Crashed {
+                        error: error.to_owned(),
+                    }
+                } else {
+                    TerminationReason::Killed
+                };
+                if let Err(e) = self.terminate(child, reason).await {
+                    log::error!("Failed to kill actor {child}: {e}");
+                }
+            }
+            serde_json::json!({
+                "event": "max_restarts_exceeded",
+                "child": failed,
+                "killed": affected,
+                "reason": error,
+            })
+        } else {
+            sup.restarts.push_back(current_tick);
+            for child in &affected {
+                let Some(actor) = self.actors.get_mut(child) else {
+                    continue;
+                };
+                let restarted = actor.restart(&mut self.scripts).await;
+                let ids = scripts::take_generated_ids();
+                match restarted {
+                    Ok(effects) => {
+                        // `init` starts the timers the child needs again
+                        for id in self.timers.cancel_all(child) {
+                            self.record(|| JournalEvent::TimerCancelled { id });
+                        }
+                        self.apply_effects(child, effects);
+                        self.record(|| JournalEvent::Restarted {
+                            actor: child.clone(),
+                            ids,
+                        });
+                        self.emit(|| EventKind::StateChanged {
+                            actor: child.clone(),
+                            state: self.actors[child].state.clone(),
+                        });
+                    }
+                    Err(e) => log::error!("Failed to restart actor {child}: {e}"),
+                }
+            }
+            log::info!("supervisor {supervisor} restarted {affected:?}");
+            serde_json::json!({
+                "event": "restarted",
+                "child": failed,
+                "restarted": affected,
+                "reason": error,
+            })
+        };
+        self.deliver(Message {
+            from: Some(failed.to_owned()),
+            to: supervisor.to_owned(),
+            payload: event,
+            ..Default::default()
+        });
     }
 
     pub async fn tick(&mut self) -> EosResult<()> {
         if self.paused {
             return Ok(());
         }
-        while let Some(request) = self.spawn_queue.pop() {
-            self.spawn_actor(request).await?;
+        self.current_tick += 1;
+        self.spawn_queued().await;
+        let TickPolicy {
+            mailbox_budget,
+            drain_all,
+            global_budget,
+        } = self.policy;
+
+        // how many messages each actor may handle this tick; messages sent during the tick
+        // are only delivered at its end, so the mailboxes can't grow while we work through them
+        let mut ids: Vec<String> = self.actors.keys().cloned().collect();
+        ids.sort();
+        let mut quotas: HashMap<String, usize> = HashMap::new();
+        for id in &ids {
+            let actor = &self.actors[id];
+            if actor.paused {
+                continue;
+            }
+            let available = actor.mailbox.len();
+            quotas.insert(
+                id.clone(),
+                if drain_all {
+                    available
+                } else {
+                    available.min(mailbox_budget)
+                },
+            );
         }
-        let mut actor_messages = Vec::new();
+
+        // handle messages round-robin, so a global budget is shared fairly between actors
+        let mut remaining = global_budget.unwrap_or(usize::MAX);
         let mut spawn_requests = Vec::new();
+        while remaining > 0 && quotas.values().any(|quota| *quota > 0) {
+            for id in &ids {
+                if remaining == 0 {
+                    break;
+                }
+                let Some(quota) = quotas.get_mut(id).filter(|quota| **quota > 0) else {
+                    continue;
+                };
+                *quota -= 1;
+                let Some(actor) = self.actors.get_mut(id).filter(|actor| !actor.paused) else {
+                    continue;
+                };
+                if let Some(message) = actor.mailbox.pop_front() {
+                    actor.changes.mailbox.touch();
+                    remaining -= 1;
+                    let version = actor.changes.state.version;
+                    let result = actor.run(&mut self.scripts, message.clone()).await;
+                    let changed = actor.changes.state.version != version;
+                    let ids = scripts::take_generated_ids();
+                    if let Some(journal) = &mut self.journal {
+                        journal.record(JournalEvent::Handled {
+                            actor: id.clone(),
+                            message: message.clone(),
+                            ids,
+                        });
+                    }
+                    match result {
+                        Ok(effects) => {
+                            actor.failures = 0;
+                            actor.send_queue.extend(effects.outbox);
+                            actor.expect_replies(effects.asks, self.current_tick);
+                            spawn_requests.extend(effects.spawns);
+                            self.update_timers(id, effects.timers, effects.cancelled_timers);
+                            self.update_subscriptions(id, effects.subscriptions);
+                            self.update_lifecycle(id, effects.lifecycle);
+                            if changed {
+                                self.emit(|| EventKind::StateChanged {
+                                    actor: id.clone(),
+                                    state: self.actors[id].state.clone(),
+                                });
+                            }
+                        }
+                        Err(e) => self.handle_failure(id, message, e).await,
+                    }
+                }
+            }
+        }
+
+        let mut actor_messages = Vec::new();
         for actor in self.actors.values_mut() {
             if actor.paused {
                 continue;
             }
-            if let Some(msg) = actor.send_queue.pop_front() {
-                actor_messages.push(msg);
-            }
-            if let Some(message) = actor.mailbox.pop_front()
-                && let Some(response) = actor.run(&mut spawn_requests, message).await?
-            {
-                actor_messages.push(response);
-            }
+            actor_messages.extend(actor.send_queue.drain(..));
         }
+        // spawn children before delivery, so messages sent to them in the same handler arrive
+        self.spawn_queue.extend(spawn_requests);
+        self.spawn_queued().await;
         for msg in actor_messages {
-            if let Some(actor) = self.actors.get_mut(&msg.to) {
-                actor.mailbox.push_back(msg);
+            self.deliver(msg);
+        }
+        for id in std::mem::take(&mut self.exits) {
+            if let Err(e) = self.terminate(&id, TerminationReason::Exited).await {
+                log::error!("Failed to terminate actor {id}: {e}");
             }
         }
-        self.spawn_queue = spawn_requests;
-        Ok(())
+        self.fire_timers();
+        self.expire_asks();
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.commit(self.current_tick, &self.actors, &self.spawn_queue)
+        {
+            log::error!("Failed to write journal: {e}");
+        }
+        self.emit(|| EventKind::Tick);
+        self.checkpoint()
     }
 }
 
-async fn init(id: &str, script: &str) -> EosResult<rune::Value> {
-    let vm = make_vm(id, script).await?;
+async fn init(
+    id: &str,
+    script: ScriptRef<'_>,
+    scripts: &mut ScriptCache,
+) -> EosResult<(rune::Value, Effects)> {
+    let vm = scripts.vm(script)?;
     if let Ok(init) = vm.lookup_function(["init"]) {
-        Ok(init.call(()).into_result()?)
+        let (state, effects) = scripts::bind(id, || init.call(()).into_result());
+        Ok((state?, effects))
     } else {
-        empty_state()
+        Ok((empty_state()?, Default::default()))
     }
 }
 
+/// Runs `migrate` of `script` on `state`, returns `state` as is if there is none
+async fn migrate(
+    id: &str,
+    script: &str,
+    state: &JsonValue,
+    scripts: &mut ScriptCache,
+) -> EosResult<(JsonValue, Effects)> {
+    let vm = scripts.vm(ScriptRef::Inline(script))?;
+    let Ok(migrate) = vm.lookup_function(["migrate"]) else {
+        return Ok((state.clone(), Default::default()));
+    };
+    let old = serde_json::from_value::<rune::Value>(state.clone())?;
+    let (state, effects) = scripts::bind(id, || migrate.call::<rune::Value>((old,)).into_result());
+    Ok((serde_json::to_value(state?)?, effects))
+}
+
 fn empty_state() -> EosResult<rune::Value> {
     Ok(rune::Value::new(Object::new())?)
 }
 
-async fn make_vm(id: &str, script: &str) -> EosResult<rune::Vm> {
-    let mut m = Module::new();
-    {
-        let id = id.to_owned();
-        m.function("send", move |to: &str, value: rune::Value| {
-            match SYSTEM.write() {
-                Ok(mut system) => {
-                    if let Some(this) = system.actors.get_mut(&id) {
-                        match serde_json::to_value(value) {
-                            Ok(payload) => {
-                                this.send_queue.push_back(Message {
-                                    from: Some(id.to_owned()),
-                                    to: to.to_owned(),
-                                    payload,
-                                });
-                            }
-                            Err(e) => {
-                                log::error!("Failed to serialize message payload: {}", e);
-                            }
-                        }
-                    } else {
-                        log::warn!("Actor died after sending a message");
-                    }
-                }
-                Err(e) => {
-                    log::error!("Failed to acquire write lock on SYSTEM: {}", e);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    async fn spawn(sys: &mut System, id: &str, script: &str) {
+        sys.spawn_actor(Props {
+            script: script.to_owned(),
+            id: Some(id.to_owned()),
+            ..Default::default()
         })
-        .build()?;
+        .await
+        .unwrap();
     }
-    {
-        m.function("plot", |value: &str| teleplot(value)).build()?;
+
+    fn send(sys: &mut System, to: &str, payload: JsonValue) {
+        sys.deliver(Message {
+            to: to.to_owned(),
+            payload,
+            ..Default::default()
+        });
     }
 
-    let mut context = Context::with_default_modules()?;
-    context.install(m)?;
+    fn state<'a>(sys: &'a System, id: &str) -> &'a JsonValue {
+        &sys.actors[id].state
+    }
 
-    let runtime = Arc::new(context.runtime()?);
-    let mut sources = Sources::new();
-    sources.insert(Source::memory(&script)?)?;
+    const COUNTER: &str = "
+        pub fn init() { #{ n: 0 } }
+        pub fn handle(state, msg) { state.n += 1; state }
+    ";
 
-    let mut diagnostics = Diagnostics::new();
+    #[tokio::test]
+    async fn handles_as_many_messages_per_tick_as_the_budget_allows() {
+        let mut sys = System::new();
+        spawn(&mut sys, "counter", COUNTER).await;
+        for n in 0..3 {
+            send(&mut sys, "counter", json!(n));
+        }
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "counter")["n"], 1);
 
-    let result = rune::prepare(&mut sources)
-        .with_context(&context)
-        .with_diagnostics(&mut diagnostics)
-        .build();
+        sys.policy.drain_all = true;
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "counter")["n"], 3);
+    }
 
-    if !diagnostics.is_empty() {
-        let mut writer = StandardStream::stderr(ColorChoice::Always);
-        diagnostics.emit(&mut writer, &sources)?;
+    #[tokio::test]
+    async fn delivers_messages_sent_in_a_tick_at_its_end() {
+        let mut sys = System::new();
+        spawn(&mut sys, "counter", COUNTER).await;
+        spawn(
+            &mut sys,
+            "forward",
+            r#"pub fn handle(state, msg) { send("counter", msg); state }"#,
+        )
+        .await;
+        send(&mut sys, "forward", json!(1));
+        sys.tick().await.unwrap();
+        assert_eq!(sys.actors["counter"].mailbox.len(), 1);
+        assert_eq!(state(&sys, "counter")["n"], 0);
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "counter")["n"], 1);
     }
 
-    let unit = result?;
-    Ok(Vm::new(runtime, Arc::new(unit)))
+    #[test]
+    fn rejects_ids_that_leave_their_directory_or_are_reserved() {
+        for id in ["counter", "worker-1", "a.b", "$clients"] {
+            assert!(check_id(id).is_ok(), "{id}");
+        }
+        for id in ["", "a/b", "..", "a..b", "a\0b", ASK_CLIENT] {
+            assert!(
+                matches!(check_id(id), Err(EosError::InvalidId(_))),
+                "{id:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn restarts_failed_children_with_the_effects_of_their_init() {
+        let mut sys = System::new();
+        spawn(&mut sys, "sup", "pub fn handle(state, msg) { state }").await;
+        spawn(&mut sys, "log", COUNTER).await;
+        sys.spawn_actor(Props {
+            script: r#"
+                pub fn init() { send("log", "started"); #{ n: 0 } }
+                pub fn handle(state, msg) {
+                    if msg == "crash" { panic("crashed") }
+                    state.n += 1;
+                    state
+                }
+            "#
+            .to_owned(),
+            id: Some("child".to_owned()),
+            supervisor: Some("sup".to_owned()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        sys.policy.drain_all = true;
+        send(&mut sys, "child", json!("count"));
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "child")["n"], 1);
+
+        send(&mut sys, "child", json!("crash"));
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "child")["n"], 0);
+        assert_eq!(sys.actors["sup"].mailbox[0].payload["event"], "restarted");
+        sys.tick().await.unwrap();
+        // once when it was spawned, and once when it was restarted
+        assert_eq!(state(&sys, "log")["n"], 2);
+    }
+
+    #[tokio::test]
+    async fn answers_asks_with_a_reply_or_a_timeout() {
+        let mut sys = System::new();
+        spawn(
+            &mut sys,
+            "echo",
+            "pub fn handle(state, msg) { (state, #{ echo: msg }) }",
+        )
+        .await;
+        spawn(&mut sys, "silent", "pub fn handle(state, msg) { state }").await;
+        spawn(
+            &mut sys,
+            "asker",
+            r#"
+                pub fn init() {
+                    ask("echo", "hi", 5);
+                    ask("silent", "hi", 4);
+                    #{ replies: [], timeouts: 0 }
+                }
+                pub fn handle_reply(state, id, reply) { state.replies.push(reply.echo); state }
+                pub fn handle_timeout(state, id) { state.timeouts += 1; state }
+            "#,
+        )
+        .await;
+        sys.policy.drain_all = true;
+        let mut client = sys.ask("echo", json!("client"), 5).unwrap();
+
+        sys.tick().await.unwrap();
+        sys.tick().await.unwrap();
+        assert_eq!(client.try_recv().unwrap(), json!({ "echo": "client" }));
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "asker")["replies"], json!(["hi"]));
+        assert_eq!(state(&sys, "asker")["timeouts"], 0);
+
+        sys.tick().await.unwrap();
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "asker")["timeouts"], 1);
+    }
+
+    const OBSERVER: &str = r#"
+        pub fn handle(state, msg) { state }
+        pub fn handle_terminated(state, id, reason) {
+            state.terminated = `${id} ${reason.kind}`;
+            state
+        }
+    "#;
+
+    #[tokio::test]
+    async fn tells_watchers_and_trapping_links_when_an_actor_terminates() {
+        let mut sys = System::new();
+        spawn(&mut sys, "worker", "pub fn handle(state, msg) { state }").await;
+        let observer = |calls: &str| format!("pub fn init() {{ {calls} #{{}} }}\n{OBSERVER}");
+        spawn(&mut sys, "watcher", &observer(r#"watch("worker");"#)).await;
+        spawn(
+            &mut sys,
+            "trapper",
+            &observer(r#"link("worker"); trap_exit(true);"#),
+        )
+        .await;
+        spawn(&mut sys, "linked", &observer(r#"link("worker");"#)).await;
+        sys.policy.drain_all = true;
+
+        sys.kill_actor("worker").await.unwrap();
+        sys.tick().await.unwrap();
+        assert_eq!(state(&sys, "watcher")["terminated"], "worker killed");
+        assert_eq!(state(&sys, "trapper")["terminated"], "worker killed");
+        assert!(!sys.actors.contains_key("linked"));
+    }
 }
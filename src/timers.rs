@@ -0,0 +1,290 @@
+//! Messages actors schedule for later with `send_after` and `schedule_every`
+//!
+//! Timers are kept in a [`TimerWheel`] that is part of the system, so they are
+//! snapshotted and restored along with everything else. A timer counts either ticks
+//! or milliseconds: tick timers sit in the slot of the tick they are due in, timers
+//! counting milliseconds in a queue ordered by the time they are due at. At the end
+//! of every tick, the system takes the timers that are due and delivers their
+//! messages, so a message due in tick `n` is handled in tick `n + 1`, just like one
+//! sent with `send` in tick `n`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::Message;
+
+/// How long a timer waits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delay {
+    Ticks(u64),
+    Millis(u64),
+}
+
+impl Delay {
+    /// Parses the delay scripts pass: a number of ticks, or a string like `"500ms"` or `"2s"`
+    pub fn parse(value: &rune::Value) -> Result<Self, String> {
+        if let Ok(ticks) = rune::from_value::<u64>(value) {
+            return Ok(Delay::Ticks(ticks));
+        }
+        let Ok(text) = rune::from_value::<String>(value) else {
+            return Err("a delay is a number of ticks or a string like \"500ms\"".to_owned());
+        };
+        let text = text.trim();
+        let (number, factor) = if let Some(ms) = text.strip_suffix("ms") {
+            (ms, 1)
+        } else if let Some(s) = text.strip_suffix('s') {
+            (s, 1000)
+        } else {
+            return text
+                .parse()
+                .map(Delay::Ticks)
+                .map_err(|_| format!("invalid delay {text:?}"));
+        };
+        let number = number
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("invalid delay {text:?}"))?;
+        number
+            .checked_mul(factor)
+            .map(Delay::Millis)
+            .ok_or_else(|| format!("delay {text:?} is too long"))
+    }
+
+    /// When a timer with this delay started at `tick` and `now` is due, `None` if that
+    /// is too far off to be represented
+    fn due(self, tick: u64, now: SystemTime) -> Option<Due> {
+        match self {
+            Delay::Ticks(ticks) => tick.checked_add(ticks).map(Due::Tick),
+            Delay::Millis(ms) => now.checked_add(Duration::from_millis(ms)).map(Due::Time),
+        }
+    }
+}
+
+/// A message an actor wants delivered later, or over and over again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timer {
+    pub id: String,
+    /// sent from the actor that scheduled the timer
+    pub message: Message,
+    pub delay: Delay,
+    /// sends the message again every `delay` until the timer is cancelled
+    pub repeat: bool,
+}
+
+impl Timer {
+    /// The actor that scheduled the timer
+    pub fn owner(&self) -> &str {
+        self.message.from.as_deref().unwrap_or_default()
+    }
+}
+
+/// When a timer is due
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Due {
+    /// at the end of this tick
+    Tick(u64),
+    /// at the end of the first tick after this time
+    Time(SystemTime),
+}
+
+/// A timer together with when it is due next
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scheduled {
+    #[serde(flatten)]
+    pub timer: Timer,
+    pub due: Due,
+}
+
+/// The timers of all actors, by when they are due
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(from = "Vec<Scheduled>", into = "Vec<Scheduled>")]
+pub struct TimerWheel {
+    timers: BTreeMap<String, Scheduled>,
+    /// ids of the tick timers, by the tick they are due in
+    slots: BTreeMap<u64, BTreeSet<String>>,
+    /// ids of the timers counting milliseconds, by the time they are due at
+    queue: BTreeSet<(SystemTime, String)>,
+}
+
+impl From<Vec<Scheduled>> for TimerWheel {
+    fn from(timers: Vec<Scheduled>) -> Self {
+        let mut wheel = TimerWheel::default();
+        for scheduled in timers {
+            wheel.insert(scheduled);
+        }
+        wheel
+    }
+}
+
+impl From<TimerWheel> for Vec<Scheduled> {
+    fn from(wheel: TimerWheel) -> Self {
+        wheel.timers.into_values().collect()
+    }
+}
+
+impl TimerWheel {
+    /// Schedules `timer`, counting its delay from `tick` and `now`
+    ///
+    /// Returns when the timer is due, or `None` without scheduling it if its delay
+    /// ends too far off to be represented.
+    pub fn schedule(&mut self, timer: Timer, tick: u64, now: SystemTime) -> Option<Due> {
+        let due = timer.delay.due(tick, now)?;
+        self.insert(Scheduled { timer, due });
+        Some(due)
+    }
+
+    /// Removes the timer `id` and returns it, `None` if there is no such timer
+    pub fn cancel(&mut self, id: &str) -> Option<Timer> {
+        let scheduled = self.timers.remove(id)?;
+        match scheduled.due {
+            Due::Tick(tick) => {
+                if let Some(slot) = self.slots.get_mut(&tick) {
+                    slot.remove(id);
+                    if slot.is_empty() {
+                        self.slots.remove(&tick);
+                    }
+                }
+            }
+            Due::Time(time) => {
+                self.queue.remove(&(time, id.to_owned()));
+            }
+        }
+        Some(scheduled.timer)
+    }
+
+    /// The timer `id`, `None` if there is no such timer
+    pub fn get(&self, id: &str) -> Option<&Scheduled> {
+        self.timers.get(id)
+    }
+
+    /// Removes every timer scheduled by `owner`, returns their ids
+    pub fn cancel_all(&mut self, owner: &str) -> Vec<String> {
+        let ids: Vec<String> = self.of(owner).map(|s| s.timer.id.clone()).collect();
+        for id in &ids {
+            self.cancel(id);
+        }
+        ids
+    }
+
+    /// The timers scheduled by `owner`, ordered by id
+    pub fn of<'a>(&'a self, owner: &'a str) -> impl Iterator<Item = &'a Scheduled> {
+        self.timers
+            .values()
+            .filter(move |scheduled| scheduled.timer.owner() == owner)
+    }
+
+    /// Takes the timers due at the end of `tick`, in the order they were due
+    ///
+    /// Repeating timers are scheduled again, at least one tick later.
+    pub fn take_due(&mut self, tick: u64, now: SystemTime) -> Vec<Timer> {
+        let mut ids: Vec<String> = Vec::new();
+        while let Some(entry) = self.slots.first_entry() {
+            if *entry.key() > tick {
+                break;
+            }
+            ids.extend(entry.remove());
+        }
+        while let Some((time, _)) = self.queue.first() {
+            if *time > now {
+                break;
+            }
+            if let Some((_, id)) = self.queue.pop_first() {
+                ids.push(id);
+            }
+        }
+        let mut due = Vec::with_capacity(ids.len());
+        for id in ids {
+            let Some(Scheduled { timer, .. }) = self.timers.remove(&id) else {
+                continue;
+            };
+            if timer.repeat {
+                let delay = match timer.delay {
+                    Delay::Ticks(ticks) => Delay::Ticks(ticks.max(1)),
+                    delay => delay,
+                };
+                match delay.due(tick, now) {
+                    Some(due) => self.insert(Scheduled {
+                        timer: timer.clone(),
+                        due,
+                    }),
+                    None => log::warn!("timer {} is due too far off to repeat", timer.id),
+                }
+            }
+            due.push(timer);
+        }
+        due
+    }
+
+    /// Adds a timer that is due at `scheduled.due`, replacing the timer with its id
+    pub fn insert(&mut self, scheduled: Scheduled) {
+        self.cancel(&scheduled.timer.id);
+        let id = scheduled.timer.id.clone();
+        match scheduled.due {
+            Due::Tick(tick) => {
+                self.slots.entry(tick).or_default().insert(id.clone());
+            }
+            Due::Time(time) => {
+                self.queue.insert((time, id.clone()));
+            }
+        }
+        self.timers.insert(id, scheduled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: impl rune::ToValue) -> Result<Delay, String> {
+        Delay::parse(&rune::to_value(value).unwrap())
+    }
+
+    #[test]
+    fn parses_ticks() {
+        assert_eq!(parse(3u64), Ok(Delay::Ticks(3)));
+        assert_eq!(parse("7".to_owned()), Ok(Delay::Ticks(7)));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse("500ms".to_owned()), Ok(Delay::Millis(500)));
+        assert_eq!(parse(" 2s ".to_owned()), Ok(Delay::Millis(2000)));
+        assert_eq!(parse("0 ms".to_owned()), Ok(Delay::Millis(0)));
+    }
+
+    #[test]
+    fn rejects_invalid_delays() {
+        assert!(parse("soon".to_owned()).is_err());
+        assert!(parse("ms".to_owned()).is_err());
+        assert!(parse("-1s".to_owned()).is_err());
+        assert!(parse(1.5f64).is_err());
+    }
+
+    #[test]
+    fn rejects_delays_that_overflow() {
+        assert_eq!(
+            parse(format!("{}ms", u64::MAX)),
+            Ok(Delay::Millis(u64::MAX))
+        );
+        assert!(parse(format!("{}s", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn skips_timers_due_too_far_off() {
+        let timer = Timer {
+            id: "t".to_owned(),
+            message: Message::default(),
+            delay: Delay::Ticks(u64::MAX),
+            repeat: false,
+        };
+        let mut wheel = TimerWheel::default();
+        assert_eq!(wheel.schedule(timer, 1, SystemTime::now()), None);
+        assert!(wheel.get("t").is_none());
+    }
+}
@@ -0,0 +1,181 @@
+//! Named topics actors subscribe to and publish messages on
+//!
+//! Topic names are made of segments separated by dots, like `sensors.kitchen.temp`.
+//! Actors subscribe to patterns, in which a `*` segment matches exactly one segment
+//! and a `#` segment matches any number of them, so `sensors.*.temp`, `sensors.#` and
+//! `#` all receive what is published on `sensors.kitchen.temp`. A published message
+//! is delivered to every actor with a matching pattern, once even if several match.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+/// Checks that `topic` is a name messages can be published on
+pub fn check_topic(topic: &str) -> Result<(), String> {
+    check(topic)?;
+    if topic
+        .split('.')
+        .any(|segment| segment == "*" || segment == "#")
+    {
+        return Err(format!(
+            "can't publish on {topic:?}, wildcards are only allowed when subscribing"
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that `pattern` is a topic name, possibly containing `*` and `#` segments
+pub fn check_pattern(pattern: &str) -> Result<(), String> {
+    check(pattern)
+}
+
+fn check(name: &str) -> Result<(), String> {
+    for segment in name.split('.') {
+        if segment.is_empty() {
+            return Err(format!("invalid topic {name:?}, it has an empty segment"));
+        }
+        if segment.contains('/') {
+            return Err(format!("invalid topic {name:?}, it contains a '/'"));
+        }
+        if segment.len() > 1 && (segment.contains('*') || segment.contains('#')) {
+            return Err(format!(
+                "invalid topic {name:?}, '*' and '#' must be whole segments"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Whether messages published on `topic` go to subscribers of `pattern`
+pub fn matches(pattern: &str, topic: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let topic: Vec<&str> = topic.split('.').collect();
+    matches_segments(&pattern, &topic)
+}
+
+fn matches_segments(pattern: &[&str], topic: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => topic.is_empty(),
+        Some((&"#", rest)) => (0..=topic.len()).any(|skip| matches_segments(rest, &topic[skip..])),
+        Some((segment, rest)) => topic.split_first().is_some_and(|(first, topic)| {
+            (*segment == "*" || segment == first) && matches_segments(rest, topic)
+        }),
+    }
+}
+
+/// The subscriptions of all actors
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Topics {
+    /// the actors subscribed to each pattern
+    subscriptions: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Topics {
+    /// Subscribes `actor` to `pattern`, returns false if it already was
+    pub fn subscribe(&mut self, pattern: &str, actor: &str) -> bool {
+        self.subscriptions
+            .entry(pattern.to_owned())
+            .or_default()
+            .insert(actor.to_owned())
+    }
+
+    /// Unsubscribes `actor` from `pattern`, returns false if it wasn't subscribed
+    pub fn unsubscribe(&mut self, pattern: &str, actor: &str) -> bool {
+        let Some(actors) = self.subscriptions.get_mut(pattern) else {
+            return false;
+        };
+        let removed = actors.remove(actor);
+        if actors.is_empty() {
+            self.subscriptions.remove(pattern);
+        }
+        removed
+    }
+
+    /// Removes all subscriptions of `actor`, returns false if it had none
+    pub fn unsubscribe_all(&mut self, actor: &str) -> bool {
+        let mut removed = false;
+        self.subscriptions.retain(|_, actors| {
+            removed |= actors.remove(actor);
+            !actors.is_empty()
+        });
+        removed
+    }
+
+    /// The actors a message published on `topic` is delivered to, ordered by id
+    pub fn subscribers(&self, topic: &str) -> BTreeSet<&str> {
+        self.subscriptions
+            .iter()
+            .filter(|(pattern, _)| matches(pattern, topic))
+            .flat_map(|(_, actors)| actors.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// The actors subscribed to exactly `pattern`
+    pub fn get(&self, pattern: &str) -> Option<&BTreeSet<String>> {
+        self.subscriptions.get(pattern)
+    }
+
+    /// The patterns actors are subscribed to, ordered by name
+    pub fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.subscriptions.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_names() {
+        assert!(matches("sensors.kitchen.temp", "sensors.kitchen.temp"));
+        assert!(!matches("sensors.kitchen.temp", "sensors.kitchen"));
+        assert!(!matches("sensors.kitchen", "sensors.kitchen.temp"));
+    }
+
+    #[test]
+    fn star_matches_one_segment() {
+        assert!(matches("sensors.*.temp", "sensors.kitchen.temp"));
+        assert!(!matches("sensors.*.temp", "sensors.temp"));
+        assert!(!matches("sensors.*", "sensors.kitchen.temp"));
+    }
+
+    #[test]
+    fn hash_matches_any_number_of_segments() {
+        assert!(matches("#", "sensors.kitchen.temp"));
+        assert!(matches("sensors.#", "sensors"));
+        assert!(matches("sensors.#", "sensors.kitchen.temp"));
+        assert!(matches("sensors.#.temp", "sensors.temp"));
+        assert!(matches("sensors.#.temp", "sensors.a.b.temp"));
+        assert!(!matches("sensors.#.temp", "sensors.a.b.hum"));
+    }
+
+    #[test]
+    fn checks_names() {
+        assert!(check_topic("sensors.kitchen").is_ok());
+        assert!(check_topic("sensors.*").is_err());
+        assert!(check_topic("sensors..temp").is_err());
+        assert!(check_pattern("sensors.*.#").is_ok());
+        assert!(check_pattern("sensors.k*").is_err());
+        assert!(check_pattern("sensors/kitchen").is_err());
+    }
+
+    #[test]
+    fn delivers_once_to_each_subscriber() {
+        let mut topics = Topics::default();
+        assert!(topics.subscribe("sensors.#", "a"));
+        assert!(topics.subscribe("sensors.*.temp", "a"));
+        assert!(!topics.subscribe("sensors.#", "a"));
+        topics.subscribe("#", "b");
+        assert_eq!(
+            topics.subscribers("sensors.kitchen.temp"),
+            BTreeSet::from(["a", "b"])
+        );
+        assert!(topics.unsubscribe_all("a"));
+        assert_eq!(
+            topics.subscribers("sensors.kitchen.temp"),
+            BTreeSet::from(["b"])
+        );
+        assert_eq!(topics.patterns().collect::<Vec<_>>(), ["#"]);
+    }
+}